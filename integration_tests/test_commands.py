@@ -0,0 +1,60 @@
+"""
+https://www.sindome.org/moo-manual.html#the-built-in-command-parser
+The Built-in Command Parser
+"""
+
+from .conftest import Connect
+
+
+def test_command_not_understood(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ frobnicate the widget
+        I couldn't understand that.
+        """
+    )
+
+
+def test_command_parts(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;add_verb(N1, [N1, "rx", "show"], ["any", "any", "any"])
+        $ ;set_verb_code(N1, "show", "throw [verb, args, argstr, dobjstr, prepstr, iobjstr]")
+        => []
+        $ show  "big thing"  in front of  the box
+        !! ["show", ["big thing", "in", "front", "of", "the", "box"], "\\"big thing\\"  in front of  the box", "big thing", "in front of", "the box"]
+        $ show the box
+        !! ["show", ["the", "box"], "the box", "the box", "", ""]
+        """
+    )
+
+
+def test_command_argspec(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;add_verb(N1, [N1, "rx", "sh*out"], ["any", "none", "none"])
+        $ ;set_verb_code(N1, "sh*out", "throw [verb, dobjstr]")
+        => []
+        $ sh hi there
+        !! ["sh", "hi there"]
+        $ shout hi with stuff
+        I couldn't understand that.
+        """
+    )
+
+
+def test_match_object_special_names(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;match_object("")
+        => N-1
+        $ ;match_object("me")
+        => N1
+        $ ;match_object("#0")
+        => N0
+        $ ;match_object("#9999") == Cfailed_match
+        => true
+        $ ;match_object("lamp") == Cfailed_match
+        => true
+        """
+    )
@@ -0,0 +1,323 @@
+"""
+https://www.sindome.org/moo-manual.html#operations-on-network-connections
+Built-in Functions / Operations on Network Connections
+"""
+
+import pexpect
+
+from .conftest import Connect, Client
+
+
+def setup_login(wizard: Client) -> None:
+    # N2 is a player called alice, and anyone else is told off
+    wizard.cram(
+        """
+        $ ;let p = create(N0, N1); set_player_flag(p, true)
+        $ ;add_verb(N0, [N1, "rx", "do_login_command"], ["this", "none", "this"])
+        $ ;set_verb_code(N0, "do_login_command", "if args[1] == \\"alice\\" { return toobj(2); } notify(player, \\"Unknown player\\")")
+        => []
+        """
+    )
+
+
+def test_notify(connect: Connect) -> None:
+    wizard = connect()
+    setup_login(wizard)
+    alice = connect()
+    alice.cram(
+        """
+        $ connect bob
+        Unknown player
+        $ connect alice
+        *** Connected ***
+        """
+    )
+    wizard.cram(
+        """
+        $ ;notify(N2, "hello\\nthere")
+        $ ;notify(N2, "")
+        """
+    )
+    alice.cram(
+        """
+        hello
+        there
+
+        $ ;notify(N2, "me")
+        me
+        """
+    )
+
+
+def test_notify_other_player(connect: Connect) -> None:
+    wizard = connect()
+    setup_login(wizard)
+    connect().cram(
+        """
+        $ connect alice
+        *** Connected ***
+        $ ;notify(N1, "hi")
+        !! E_PERM
+        """
+    )
+
+
+def test_connected_players(connect: Connect) -> None:
+    wizard = connect()
+    setup_login(wizard)
+    connect().cram(
+        """
+        $ connect bob
+        Unknown player
+        """
+    )
+    alice = connect()
+    alice.cram(
+        """
+        $ connect alice
+        *** Connected ***
+        $ ;connected_players()
+        => [N1, N2]
+        $ ;connected_players(true)
+        !! E_PERM
+        """
+    )
+    wizard.cram(
+        """
+        $ ;connected_players(true).len()
+        => 3
+        """
+    )
+
+
+def test_connection_info(connect: Connect) -> None:
+    wizard = connect()
+    setup_login(wizard)
+    connect().cram(
+        """
+        $ connect alice
+        *** Connected ***
+        $ ;connection_name(N2).starts_with("port 8888 from 127.0.0.1, port ")
+        => true
+        $ ;connected_seconds(N2) >= idle_seconds(N2)
+        => true
+        $ ;connection_name(N1)
+        !! E_PERM
+        """
+    )
+    wizard.cram(
+        """
+        $ ;idle_seconds(N3)
+        !! E_INVARG
+        """
+    )
+
+
+def setup_hooks(wizard: Client) -> None:
+    # Each hook tells the wizard it was called
+    wizard.cram(
+        """
+        $ ;for h in ["user_connected", "user_reconnected", "user_disconnected", "user_client_disconnected"] { add_verb(N0, [N1, "rx", h], ["this", "none", "this"]); set_verb_code(N0, h, "notify(N1, verb + \\" \\" + tostr([args[0]]))"); }
+        """
+    )
+
+
+def test_boot_player(connect: Connect) -> None:
+    wizard = connect()
+    setup_login(wizard)
+    setup_hooks(wizard)
+    connect().cram(
+        """
+        $ connect alice
+        *** Connected ***
+        $ ;boot_player(N2)
+        *** Disconnected ***
+        """
+    )
+    wizard.cram(
+        """
+        user_connected N2
+        user_disconnected N2
+        $ ;connected_players()
+        => [N1]
+        """
+    )
+
+
+def test_boot_other_player(connect: Connect) -> None:
+    wizard = connect()
+    setup_login(wizard)
+    setup_hooks(wizard)
+    alice = connect()
+    alice.cram(
+        """
+        $ connect alice
+        *** Connected ***
+        """
+    )
+    wizard.cram(
+        """
+        user_connected N2
+        $ ;boot_player(N2)
+        user_disconnected N2
+        $ ;boot_player(N2)
+        $ ;connected_players()
+        => [N1]
+        """
+    )
+    alice.cram(
+        """
+        *** Disconnected ***
+        """
+    )
+    alice.expect(pexpect.EOF)
+
+
+def test_boot_player_with_suspended_task(connect: Connect) -> None:
+    # Tasks still holding on to the connection don't keep the socket open
+    wizard = connect()
+    setup_login(wizard)
+    alice = connect()
+    alice.cram(
+        """
+        $ connect alice
+        *** Connected ***
+        $ ;fork(0, || suspend()); boot_player(N2)
+        *** Disconnected ***
+        """
+    )
+    alice.expect(pexpect.EOF)
+
+
+def test_boot_player_not_allowed(connect: Connect) -> None:
+    wizard = connect()
+    setup_login(wizard)
+    connect().cram(
+        """
+        $ connect alice
+        *** Connected ***
+        $ ;boot_player(N1)
+        !! E_PERM
+        """
+    )
+
+
+def test_reconnect(connect: Connect) -> None:
+    wizard = connect()
+    setup_login(wizard)
+    setup_hooks(wizard)
+    first = connect()
+    first.cram(
+        """
+        $ connect alice
+        *** Connected ***
+        """
+    )
+    connect().cram(
+        """
+        $ connect alice
+        *** Redirected ***
+        """
+    )
+    first.cram(
+        """
+        *** Redirecting connection to new port ***
+        """
+    )
+    wizard.cram(
+        """
+        user_connected N2
+        user_reconnected N2
+        """
+    )
+
+
+def test_bootstrap_reconnect(connect: Connect) -> None:
+    # Without a way to log in, every connection is the wizard, so a new one takes over the old one
+    first = connect()
+    setup_hooks(first)
+    second = connect()
+    second.cram(
+        """
+        user_reconnected N1
+        $ ;connected_players()
+        => [N1]
+        """
+    )
+    first.cram(
+        """
+        *** Redirecting connection to new port ***
+        """
+    )
+    first.expect(pexpect.EOF)
+
+
+def test_client_disconnected(connect: Connect) -> None:
+    wizard = connect()
+    setup_login(wizard)
+    setup_hooks(wizard)
+    alice = connect()
+    alice.cram(
+        """
+        $ connect alice
+        *** Connected ***
+        """
+    )
+    alice.close()
+    wizard.cram(
+        """
+        user_connected N2
+        user_client_disconnected N2
+        """
+    )
+
+
+def test_read(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let o = create(N0, N1); add_verb(o, [N1, "rx", "ask"], ["this", "none", "this"])
+        $ ;set_verb_code(N2, "ask", "notify(player, \\"Name?\\"); let n = read(); notify(player, \\"Hi \\" + n); n")
+        => []
+        $ ;N2.ask()
+        Name?
+        $ ;bob the builder
+        Hi ;bob the builder
+        => ";bob the builder"
+        $ ;1
+        => 1
+        """
+    )
+
+
+def test_read_sent_early(connect: Connect) -> None:
+    # Lines sent before read() is called still go to the task, not to the command parser
+    connect().cram(
+        """
+        $ ;let o = create(N0, N1); add_verb(o, [N1, "rx", "ask"], ["this", "none", "this"])
+        $ ;set_verb_code(N2, "ask", "notify(player, \\"Name?\\"); notify(player, \\"Hi \\" + read()); ()")
+        => []
+        $ ;N2.ask()
+        $ bob
+        Name?
+        Hi bob
+        $ ;1
+        => 1
+        """
+    )
+
+
+def test_read_forked(connect: Connect) -> None:
+    # Forked tasks get the next line too, instead of the command parser
+    connect().cram(
+        """
+        $ ;let o = create(N0, N1); add_verb(o, [N1, "rx", "ask"], ["this", "none", "this"])
+        $ ;set_verb_code(N2, "ask", "fork(0, || { notify(player, \\"Name?\\"); notify(player, \\"Hi \\" + read()); }); ()")
+        => []
+        $ ;N2.ask()
+        Name?
+        $ bob
+        Hi bob
+        $ ;1
+        => 1
+        """
+    )
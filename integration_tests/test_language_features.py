@@ -46,7 +46,7 @@ def test_rhai_variable_isolation(connect: Connect) -> None:
     c2.cram(
         """
     $ ;x
-    Variable not found: x (line 1, position 1) in call to function eval (line 1, position 16)
+    Variable not found: x (line 1, position 1)
     """
     )
 
@@ -0,0 +1,34 @@
+"""
+https://www.sindome.org/moo-manual.html#server-commands-and-database-assumptions
+Server Commands and Database Assumptions / Logging in
+"""
+
+from .conftest import Connect
+
+
+def test_login(connect: Connect) -> None:
+    wizard = connect()
+    wizard.cram(
+        """
+        $ ;let p = create(N0, N1)
+        $ ;set_player_flag(p, true)
+        $ ;is_player(p)
+        => true
+        $ ;let q = create(N0, N1)
+        $ ;add_verb(N0, [N1, "rx", "do_login_command"], ["this", "none", "this"])
+        $ ;set_verb_code(N0, "do_login_command", "if args[1] == \\"alice\\" { return toobj(2); } if args[1] == \\"bob\\" { return toobj(3); } player")
+        => []
+        """
+    )
+    connect().cram(
+        """
+        $ connect bob
+        $ connect carol
+        $ ;1 + 1
+        $ connect alice
+        *** Connected ***
+        $ ;match_object("me")
+        => N2
+        """
+    )
+
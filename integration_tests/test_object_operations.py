@@ -89,3 +89,109 @@ def test_chparent(connect: Connect):
         => N3
         """
     )
+
+
+def test_children_ancestors_descendants(connect: Connect):
+    connect().cram(
+        """
+        $ ;let a = create(N0, N0); let b = create(a, N0); let c = create(a, N0); let d = create(b, N0); [children(a), descendants(a), ancestors(d)]
+        => [[N3, N4], [N3, N5, N4], [N3, N2, N0]]
+        $ ;[children(N5), descendants(N5), ancestors(N0)]
+        => [[], [], []]
+        $ ;children(toobj(-1))
+        !! E_INVARG
+        """
+    )
+
+
+def test_chparent_children(connect: Connect):
+    connect().cram(
+        """
+        $ ;let a = create(N0, N0); let b = create(a, N0); let c = create(a, N0); let d = create(b, N0)
+        $ ;chparent(N3, N4); [children(N2), children(N4), descendants(N2), ancestors(N5)]
+        => [[N4], [N3], [N4, N3, N5], [N3, N4, N2, N0]]
+        """
+    )
+
+
+def test_move(connect: Connect):
+    connect().cram(
+        """
+        $ ;let r = create(N0, N0); let b = create(N0, N0); move(b, r); [b.location, r.contents]
+        => [N2, [N3]]
+        $ ;move(N3, toobj(-1)); [N3.location, N2.contents]
+        => [N-1, []]
+        $ ;move(N3, toobj(99))
+        !! E_INVARG
+        $ ;toobj(99).location
+        !! E_INVIND
+        """
+    )
+
+
+def test_move_recursive(connect: Connect):
+    connect().cram(
+        """
+        $ ;let r = create(N0, N0); let b = create(N0, N0); move(b, r)
+        $ ;move(N2, N2)
+        !! E_RECMOVE
+        $ ;move(N2, N3)
+        !! E_RECMOVE
+        """
+    )
+
+
+def test_move_accept(connect: Connect):
+    # Non-wizards can only move things into objects that accept them
+    connect().cram(
+        """
+        $ ;let r = create(N0, N0); let p = create(N0, N0); let b = create(N0, p); let c = create(N0, p); [r, p, b, c]
+        => [N2, N3, N4, N5]
+        """
+    )
+    connect().cram(
+        """
+        $ ;set_task_perms(N3); move(N4, N2)
+        !! E_NACC
+        """
+    )
+    connect().cram(
+        """
+        $ ;add_verb(N2, [N1, "rx", "accept"], ["this", "none", "this"]); set_verb_code(N2, "accept", "args[0] == toobj(4)")
+        => []
+        """
+    )
+    connect().cram(
+        """
+        $ ;set_task_perms(N3); move(N4, N2); N2.contents
+        => [N4]
+        $ ;move(N5, N2)
+        !! E_NACC
+        $ ;move(N2, N4)
+        !! E_PERM
+        """
+    )
+
+
+def test_move_enterfunc_exitfunc(connect: Connect):
+    connect().cram(
+        """
+        $ ;let r = create(N0, N0); let b = create(N0, N0); add_property(r, "log", [], [N1, "rw"])
+        $ ;add_verb(N2, [N1, "rx", "enterfunc exitfunc"], ["this", "none", "this"]); set_verb_code(N2, "enterfunc", "this.log.push([verb, args[0]]); ()")
+        => []
+        $ ;move(N3, N2); move(N3, toobj(-1)); N2.log
+        => [["enterfunc", N3], ["exitfunc", N3]]
+        """
+    )
+
+
+def test_location_contents_read_only(connect: Connect):
+    connect().cram(
+        """
+        $ ;let r = create(N0, N0); let b = create(N0, N0)
+        $ ;let b = N3; b.location = N2
+        !! E_PERM
+        $ ;let r = N2; r.contents = []
+        !! E_PERM
+        """
+    )
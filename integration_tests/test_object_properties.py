@@ -86,7 +86,7 @@ def test_add_property_object_wrong_type(connect: Connect) -> None:
     connect().cram(
         """
         $ ;add_property("this-is-not-an-obj", "testprop", "testval", [N0, ""])
-        Function not found: add_property (&str | ImmutableString | String, &str | ImmutableString | String, &str | ImmutableString | String, array) (line 1, position 1) in call to function eval (line 1, position 16)
+        Function not found: add_property (&str | ImmutableString | String, &str | ImmutableString | String, &str | ImmutableString | String, array) (line 1, position 1)
         """
     )
 
@@ -129,25 +129,55 @@ def test_add_property_already_exists(connect: Connect) -> None:
     # TODO verify correct owner, perms
 
 
-@pytest.mark.xfail(reason="chparent / parent hierarchies not yet implemented")
 def test_add_property_already_exists_on_grandparent(connect: Connect) -> None:
     connect().cram(
         """
         $ ;let o = create(N0, N0)
         $ ;let p = create(o, N0)
         $ ;let gp = create(p, N0)
-        $ ;let owner1 = create()
-        $ ;let owner2 = create()
+        $ ;let owner1 = create(N0)
+        $ ;let owner2 = create(N0)
         $ ;add_property(gp, "gprop", "val1", [owner1, "rw"])
         $ ;add_property(o, "gprop", "val2", [owner2, "wc"])
         !! E_INVARG
         $ ;o.gprop
-        => "val"
+        !! E_PROPNF
+        $ ;add_property(o, "oprop", "val1", [owner1, "rw"])
+        $ ;add_property(gp, "oprop", "val2", [owner2, "wc"])
+        !! E_INVARG
         """
     )
     # TODO verify correct owner, perms
 
 
+def test_add_property_builtin_name(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let o = create(N0, N0); add_property(o, "name", "val", [N1, "rw"])
+        !! E_INVARG
+        """
+    )
+
+
+def test_add_property_not_allowed(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let u = create(N0, N0); let mine = create(N0, u); let theirs = create(N0, N0); [u, mine, theirs]
+        => [N2, N3, N4]
+        """
+    )
+    connect().cram(
+        """
+        $ ;set_task_perms(N2); add_property(N4, "x", 1, [N2, "r"])
+        !! E_PERM
+        $ ;add_property(N3, "x", 1, [N1, "r"])
+        !! E_PERM
+        $ ;add_property(N3, "x", 1, [N2, "r"]); N3.x
+        => 1
+        """
+    )
+
+
 def test_add_property_invalid_perms(connect: Connect) -> None:
     connect().cram(
         """
@@ -207,9 +237,283 @@ def test_add_set_get_property(connect: Connect) -> None:
     )
 
 
+def test_inherited_property(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let p = create(N0, N0); add_property(p, "x", 1, [N1, "r"])
+        $ ;let c = create(N2, N0); let gc = create(c, N0); [c.x, gc.x]
+        => [1, 1]
+        $ ;add_property(N3, "y", "why", [N1, "r"]); N4.y
+        => "why"
+        $ ;add_property(N4, "x", 2, [N1, "r"])
+        !! E_INVARG
+        $ ;add_property(N2, "y", 2, [N1, "r"])
+        !! E_INVARG
+        """
+    )
+
+
+def test_inherited_property_override(connect: Connect) -> None:
+    # Setting an inherited property only changes the value for the object and its descendants
+    connect().cram(
+        """
+        $ ;let p = create(N0, N0); add_property(p, "x", 1, [N1, "rw"]); let c = create(p, N0); create(c, N0)
+        => N4
+        $ ;let p = N2; p.x = 2; [N2.x, N3.x, N4.x]
+        => [2, 2, 2]
+        $ ;let c = N3; c.x = 3; [N2.x, N3.x, N4.x]
+        => [2, 3, 3]
+        $ ;let p = N2; p.x = 4; [N2.x, N3.x, N4.x]
+        => [4, 3, 3]
+        """
+    )
+
+
+def test_chparent_properties(connect: Connect) -> None:
+    # The properties of the old ancestors are gone, the ones of the new ancestors are inherited
+    connect().cram(
+        """
+        $ ;let a = create(N0, N0); add_property(a, "a", "from a", [N1, "rw"])
+        $ ;let b = create(N0, N0); add_property(b, "b", "from b", [N1, "rw"])
+        $ ;let o = create(N2, N0); add_property(o, "o", "own", [N1, "rw"]); let c = create(o, N0); c.a
+        => "from a"
+        $ ;chparent(N4, N3); [N4.b, N5.b, N5.o]
+        => ["from b", "from b", "own"]
+        $ ;N5.a
+        !! E_PROPNF
+        $ ;let c = create(N0, N0); add_property(c, "o", 0, [N1, "r"]); chparent(N4, c)
+        !! E_INVARG
+        """
+    )
+
+
+def test_chparent_shared_ancestors(connect: Connect) -> None:
+    # Properties of the ancestors the old and new parents share keep their values
+    connect().cram(
+        """
+        $ ;let base = create(N0, N0); add_property(base, "desc", "base", [N1, "rw"]); let p = create(base, N0); add_property(p, "one", 1, [N1, "rw"]); let q = create(base, N0); let x = create(p, N0); let y = create(x, N0); x.desc = "custom"; x.one = 2; y.desc = "y"; [p, q, x, y]
+        => [N3, N4, N5, N6]
+        $ ;chparent(N5, N4); [N5.desc, N6.desc, is_clear_property(N5, "desc")]
+        => ["custom", "y", false]
+        $ ;N6.one
+        !! E_PROPNF
+        """
+    )
+
+
+def test_clear_property(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let p = create(N0, N0); add_property(p, "x", 1, [N1, "r"]); let c = create(p, N0); [is_clear_property(p, "x"), is_clear_property(c, "x")]
+        => [false, true]
+        $ ;let c = N3; c.x = 2; [is_clear_property(c, "x"), c.x]
+        => [false, 2]
+        $ ;clear_property(N3, "x"); [is_clear_property(N3, "x"), N3.x]
+        => [true, 1]
+        $ ;clear_property(N2, "x")
+        !! E_INVARG
+        $ ;clear_property(N2, "nope")
+        !! E_PROPNF
+        $ ;is_clear_property(N2, "name")
+        => false
+        $ ;clear_property(N2, "name")
+        !! E_PERM
+        """
+    )
+
+
+def test_clear_property_not_allowed(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let p = create(N0, N0); add_property(p, "x", 1, [N1, ""]); create(p, N0)
+        => N3
+        """
+    )
+    connect().cram(
+        """
+        $ ;let o = create(N0, N0); set_task_perms(o); is_clear_property(N3, "x")
+        !! E_PERM
+        """
+    )
+    connect().cram(
+        """
+        $ ;set_task_perms(N4); clear_property(N3, "x")
+        !! E_PERM
+        """
+    )
+
+
+def test_set_property_info(connect: Connect) -> None:
+    # Only the slot on the object itself changes
+    connect().cram(
+        """
+        $ ;let p = create(N0, N0); add_property(p, "x", 1, [N1, "r"]); create(p, N0)
+        => N3
+        $ ;set_property_info(N2, "x", [N0, "rw"]); [property_info(N2, "x"), property_info(N3, "x")]
+        => [[N0, "rw"], [N1, "r"]]
+        $ ;set_property_info(N2, "nope", [N0, "rw"])
+        !! E_PROPNF
+        $ ;set_property_info(N2, "x", [N0, "rwq"])
+        !! E_INVARG
+        """
+    )
+
+
+def test_set_property_info_rename(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let p = create(N0, N0); add_property(p, "x", 1, [N1, "r"]); add_property(p, "y", 2, [N1, "r"]); create(p, N0)
+        => N3
+        $ ;set_property_info(N2, "x", [N1, "r", "z"]); [N2.z, N3.z, properties(N2)]
+        => [1, 1, ["y", "z"]]
+        $ ;N3.x
+        !! E_PROPNF
+        $ ;set_property_info(N2, "z", [N1, "r", "y"])
+        !! E_INVARG
+        $ ;set_property_info(N3, "z", [N1, "r", "w"])
+        !! E_INVARG
+        """
+    )
+
+
+def test_set_property_info_not_allowed(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let p = create(N0, N0); add_property(p, "x", 1, [N1, "rw"]); create(N0, N0)
+        => N3
+        """
+    )
+    connect().cram(
+        """
+        $ ;set_task_perms(N3); set_property_info(N2, "x", [N3, "rw"])
+        !! E_PERM
+        """
+    )
+    # Owners can't give their properties away either
+    connect().cram(
+        """
+        $ ;add_property(N3, "y", 1, [N3, "rw"])
+        """
+    )
+    connect().cram(
+        """
+        $ ;set_task_perms(N3); set_property_info(N3, "y", [N3, "r"]); property_info(N3, "y")
+        => [N3, "r"]
+        $ ;set_property_info(N3, "y", [N2, "r"])
+        !! E_PERM
+        """
+    )
+
+
+def test_delete_property(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let p = create(N0, N0); add_property(p, "x", 1, [N1, "r"]); add_property(p, "y", 2, [N1, "r"]); create(p, N0)
+        => N3
+        $ ;delete_property(N3, "x")
+        !! E_PROPNF
+        $ ;delete_property(N2, "x"); N3.y
+        => 2
+        $ ;N3.x
+        !! E_PROPNF
+        $ ;delete_property(N2, "x")
+        !! E_PROPNF
+        """
+    )
+
+
+def test_properties(connect: Connect) -> None:
+    # Sorted by name, and without the inherited ones
+    connect().cram(
+        """
+        $ ;let p = create(N0, N0); add_property(p, "b", 1, [N1, "r"]); add_property(p, "a", 2, [N1, "r"]); let c = create(p, N0); [properties(p), properties(c)]
+        => [["a", "b"], []]
+        $ ;properties(toobj(-1))
+        !! E_INVARG
+        """
+    )
+
+
+def test_property_listing_and_deletion_not_allowed(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let p = create(N0, N0); add_property(p, "x", 1, [N1, "r"]); create(N0, N0)
+        => N3
+        """
+    )
+    connect().cram(
+        """
+        $ ;set_task_perms(N3); properties(N2)
+        !! E_PERM
+        """
+    )
+    connect().cram(
+        """
+        $ ;set_task_perms(N3); delete_property(N2, "x")
+        !! E_PERM
+        """
+    )
+
+
+def test_property_read_write_perms(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let u = create(N0, N0); let p = create(N0, N0); add_property(p, "secret", 1, [N1, ""]); add_property(p, "pub", 2, [N1, "r"]); add_property(p, "open", 3, [N1, "rw"])
+        """
+    )
+    connect().cram(
+        """
+        $ ;set_task_perms(N2); [N3.pub, N3.open]
+        => [2, 3]
+        $ ;N3.secret
+        !! E_PERM
+        $ ;let p = N3; p.pub = 5
+        !! E_PERM
+        $ ;let p = N3; p.open = 5; p.open
+        => 5
+        """
+    )
+
+
+def test_property_c_perm(connect: Connect) -> None:
+    # The owner of a child owns its slots for inherited properties with the c permission
+    connect().cram(
+        """
+        $ ;let u = create(N0, N0); let p = create(N0, N0); add_property(p, "mine", 1, [N1, "rc"]); add_property(p, "theirs", 2, [N1, "r"]); create(p, u)
+        => N4
+        $ ;[property_info(N4, "mine"), property_info(N4, "theirs")]
+        => [[N2, "rc"], [N1, "r"]]
+        """
+    )
+    connect().cram(
+        """
+        $ ;set_task_perms(N2); let c = N4; c.mine = 3; [c.mine, N3.mine]
+        => [3, 1]
+        $ ;let c = N4; c.theirs = 3
+        !! E_PERM
+        """
+    )
+
+
 @pytest.mark.xfail
 def test_property_info_no_read_perm(connect: Connect) -> None:
     raise NotImplementedError()
 
 
 # TODO test permissions for property manipulation
+
+
+def test_player_password_hidden_from_non_wizards(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let p = create(N0, N1); add_property(p, "password", crypt("pw"), [N1, "rw"])
+        $ ;set_player_flag(N2, true); N2.password == crypt("pw", N2.password)
+        => true
+        """
+    )
+    connect().cram(
+        """
+        $ ;set_task_perms(N2); N2.password
+        !! E_PERM
+        """
+    )
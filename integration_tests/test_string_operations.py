@@ -13,4 +13,45 @@ def test_string_hash(connect: Connect) -> None:
         $ ;string_hash("wheee")
         => "6a659751d5b9b64921a307f505d674581e1446b938e68398672573c0bcc43ec4c2c5734784b27934f9e37c31d25f8296145a02513d77c2004b5622873b185b15"
         """
-    )
\ No newline at end of file
+    )
+
+def test_crypt(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;crypt("Hello world!", "$5$saltstring")
+        => "$5$saltstring$5B8vYYiY.CVt1RlTTf8KbXBH3hsxY/GNooZaBBGWEc5"
+        $ ;crypt("Hello world!", "$6$rounds=10000$saltstringsaltstring")
+        => "$6$rounds=10000$saltstringsaltst$OW1/O6BYHV6BcXZu8QVeXbDWra3Oeqh0sbHbbMCVNSnCM/UrjmM0Dp8vOuZeHBy/YTBmSK6H9qs/y3RnOaw5v."
+        $ ;crypt("x", "$6$rounds=abc$salt")
+        !! E_INVARG
+        $ ;crypt("x", "$6$rounds=1000")
+        !! E_INVARG
+        """
+    )
+
+
+def test_crypt_des(connect: Connect) -> None:
+    # The original DES variant, for salts without a $ prefix
+    connect().cram(
+        """
+        $ ;crypt("x", "ab")
+        => "abiQ6Ep3EYTHc"
+        $ ;crypt("foo", "abQ9KY.KfrYrc")
+        => "abQ9KY.KfrYrc"
+        $ ;crypt("longpassword", "Zz") == crypt("longpass", "Zz")
+        => true
+        $ ;crypt("x", "a")
+        !! E_INVARG
+        $ ;crypt("x", "$1$salt")
+        !! E_INVARG
+        """
+    )
+
+
+def test_crypt_random_salt(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let h = crypt("secret"); [crypt("secret", h) == h, crypt("wrong", h) == h, crypt("secret") == h]
+        => [true, false, false]
+        """
+    )
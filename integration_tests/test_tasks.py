@@ -0,0 +1,216 @@
+"""
+https://www.sindome.org/moo-manual.html#moo-code-evaluation-and-task-management
+Built-in Functions / MOO-Code Evaluation and Task Manipulation
+"""
+
+from .conftest import Connect, Client
+
+
+def test_fork(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let o = create(N0, N1); add_verb(o, [N1, "rx", "later"], ["this", "none", "this"])
+        $ ;set_verb_code(N2, "later", "let me = this; fork(0, || notify(player, tostr([\\"forked from \\", me])));")
+        => []
+        $ ;N2.later(); ()
+        forked from N2
+        """
+    )
+
+
+def test_fork_callers(connect: Connect) -> None:
+    # The forked task has no callers, even though the verb that forked it had one
+    connect().cram(
+        """
+        $ ;let o = create(N0, N1); add_verb(o, [N1, "rx", "outer"], ["this", "none", "this"])
+        $ ;set_verb_code(N2, "outer", "this.inner()")
+        => []
+        $ ;add_verb(N2, [N1, "rx", "inner"], ["this", "none", "this"])
+        $ ;set_verb_code(N2, "inner", "fork(0, || notify(player, \\"forked callers: \\" + toliteral(callers())));")
+        => []
+        $ ;N2.outer(); ()
+        forked callers: []
+        """
+    )
+
+
+def test_fork_error(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let o = create(N0, N1); add_verb(o, [N1, "rx", "boom"], ["this", "none", "this"])
+        $ ;set_verb_code(N2, "boom", "fork(0, || { throw \\"oops\\"; });")
+        => []
+        $ ;N2.boom(); ()
+        Runtime error: oops (line 1, position 14)
+        in closure call
+        in call to function 'N2:boom'
+        """
+    )
+
+
+def test_queued_tasks(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let o = create(N0, N1); add_verb(o, [N1, "rx", "later"], ["this", "none", "this"])
+        $ ;set_verb_code(N2, "later", "fork(60, || 1)")
+        => []
+        $ ;let t = N2.later(); let q = queued_tasks(); [q.len(), q[0][0] == t, q[0][0] != task_id()]
+        => [1, true, true]
+        $ ;let q = queued_tasks()[0]; [q[4], q[5], q[6], q[8]]
+        => [N1, N2, "later", N2]
+        $ ;N2.later(); queued_tasks().len()
+        => 2
+        """
+    )
+
+
+def test_fork_negative_delay(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let o = create(N0, N1); add_verb(o, [N1, "rx", "later"], ["this", "none", "this"])
+        $ ;set_verb_code(N2, "later", "fork(-1, || 1)")
+        => []
+        $ ;N2.later()
+        !! E_INVARG
+        """
+    )
+
+
+def test_delay_too_long(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;fork(9223372036854775807, || 1)
+        !! E_INVARG
+        $ ;suspend(9223372036854775807)
+        !! E_INVARG
+        $ ;1
+        => 1
+        """
+    )
+
+
+def setup_sleeper(wizard: Client) -> None:
+    # N2:sleeper forks a task that suspends until it's resumed, and returns its ID
+    wizard.cram(
+        """
+        $ ;let o = create(N0, N1); add_verb(o, [N1, "rx", "sleeper"], ["this", "none", "this"])
+        $ ;set_verb_code(N2, "sleeper", "fork(0, || { let v = suspend(); notify(player, tostr([\\"resumed \\", v])); })")
+        => []
+        """
+    )
+
+
+def test_suspend_timeout(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;suspend(0)
+        => 0
+        $ ;suspend(-1)
+        !! E_INVARG
+        """
+    )
+
+
+def test_resume(connect: Connect) -> None:
+    wizard = connect()
+    setup_sleeper(wizard)
+    wizard.cram(
+        """
+        $ ;let t = N2.sleeper(); suspend(0); let q = queued_tasks(); [q.len(), q[0][0] == t, q[0][1]]
+        => [1, true, -1]
+        $ ;resume(queued_tasks()[0][0], 42); ()
+        resumed 42
+        $ ;queued_tasks()
+        => []
+        $ ;resume(task_id())
+        !! E_INVARG
+        """
+    )
+
+
+def test_suspended_command(connect: Connect) -> None:
+    # The connection takes the next commands while the previous one is suspended
+    connect().cram(
+        """
+        $ ;suspend()
+        $ ;"after"
+        => "after"
+        $ ;resume(queued_tasks()[0][0], 42); ()
+        => 42
+        """
+    )
+
+
+def test_resume_forked_task(connect: Connect) -> None:
+    # A task that hasn't started yet isn't suspended
+    connect().cram(
+        """
+        $ ;let o = create(N0, N1); add_verb(o, [N1, "rx", "later"], ["this", "none", "this"])
+        $ ;set_verb_code(N2, "later", "fork(60, || 1)")
+        => []
+        $ ;resume(N2.later())
+        !! E_INVARG
+        """
+    )
+
+
+def test_kill_task(connect: Connect) -> None:
+    wizard = connect()
+    setup_sleeper(wizard)
+    wizard.cram(
+        """
+        $ ;let t = N2.sleeper(); suspend(0); kill_task(t); queued_tasks()
+        => []
+        $ ;kill_task(task_id()); 1
+        $ ;try { kill_task(task_id()); } catch { 2 }
+        $ ;kill_task(12345)
+        !! E_INVARG
+        """
+    )
+
+
+def test_task_control_not_allowed(connect: Connect) -> None:
+    wizard = connect()
+    setup_sleeper(wizard)
+    wizard.cram(
+        """
+        $ ;add_property(N2, "task", N2.sleeper(), [N1, "r"])
+        """
+    )
+    connect().cram(
+        """
+        $ ;set_task_perms(N2); resume(N2.task)
+        !! E_PERM
+        """
+    )
+    connect().cram(
+        """
+        $ ;set_task_perms(N2); kill_task(N2.task)
+        !! E_PERM
+        """
+    )
+
+
+def test_out_of_ticks(connect: Connect) -> None:
+    # The error can't be caught, and the next command gets a fresh budget
+    connect().cram(
+        """
+        $ ;loop {}
+        Task ran out of ticks
+        $ ;try { loop {} } catch { 1 }
+        Task ran out of ticks
+        $ ;1 + 1
+        => 2
+        """
+    )
+
+
+def test_ticks_and_seconds_left(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let a = ticks_left(); let b = ticks_left(); [a > b, b > 0]
+        => [true, true]
+        $ ;let s = seconds_left(); s > 0 && s <= 5
+        => true
+        """
+    )
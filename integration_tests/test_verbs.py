@@ -0,0 +1,238 @@
+"""
+https://www.sindome.org/moo-manual.html#operations-on-verbs
+Manipulating Objects / Operations on Verbs
+"""
+
+from .conftest import Connect
+
+
+def test_add_verb_set_and_get_code(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let o = create(N0, N0)
+        $ ;add_verb(o, [N1, "rx", "foo b*ar"], ["this", "none", "this"])
+        $ ;set_verb_code(o, "foo", "let x = 1;\\nx + 1")
+        => []
+        $ ;verb_code(o, "foo")
+        => ["let x = 1;", "x + 1"]
+        $ ;verb_code(o, "ba")
+        => ["let x = 1;", "x + 1"]
+        $ ;verb_code(o, 1)
+        => ["let x = 1;", "x + 1"]
+        """
+    )
+
+
+def test_set_verb_code_compile_error(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let o = create(N0, N0)
+        $ ;add_verb(o, [N1, "rx", "foo"], ["this", "none", "this"])
+        $ ;set_verb_code(o, "foo", "let x = ;")
+        => ["Unexpected ';' (line 1, position 9)"]
+        $ ;verb_code(o, "foo")
+        => []
+        """
+    )
+
+
+def test_add_verb_invalid_args(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let o = create(N0, N0)
+        $ ;add_verb(o, [N1, "rq", "foo"], ["this", "none", "this"])
+        !! E_INVARG
+        $ ;add_verb(o, [N1, "rx", "foo"], ["this", "sideways", "this"])
+        !! E_INVARG
+        $ ;add_verb(o, [N1, "rx", ""], ["this", "none", "this"])
+        !! E_INVARG
+        $ ;add_verb(o, [N1, "rx", "foo"], ["this", "using", "any"])
+        $ ;add_verb(o, [N1, "rx", "bar"], ["this", "with/using", "any"])
+        """
+    )
+
+
+def test_add_verb_nonprogrammer(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let o = create(N0, N0)
+        $ ;set_task_perms(o)
+        $ ;add_verb(o, [o, "rx", "foo"], ["this", "none", "this"])
+        !! E_PERM
+        """
+    )
+
+
+def test_delete_verb(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let o = create(N0, N0)
+        $ ;add_verb(o, [N1, "rx", "foo"], ["this", "none", "this"])
+        $ ;delete_verb(o, "foo")
+        $ ;delete_verb(o, "foo")
+        !! E_VERBNF
+        $ ;verb_code(o, 1)
+        !! E_VERBNF
+        """
+    )
+
+
+def test_call_verb(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let o = create(N0, N0)
+        $ ;add_verb(o, [N1, "rx", "foo"], ["this", "none", "this"])
+        $ ;set_verb_code(o, "foo", "return [this, caller, player, verb, args];")
+        => []
+        $ ;o.foo(1, 2)
+        => [N2, N1, N1, "foo", [1, 2]]
+        """
+    )
+
+
+def test_call_inherited_verb(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let o = create(N0, N0)
+        $ ;let p = create(o, N0)
+        $ ;add_verb(o, [N1, "rx", "foo"], ["this", "none", "this"])
+        $ ;set_verb_code(o, "foo", "[this, caller, args]")
+        => []
+        $ ;add_verb(o, [N1, "rx", "bar"], ["this", "none", "this"])
+        $ ;set_verb_code(o, "bar", "this.foo(3)")
+        => []
+        $ ;p.bar()
+        => [N3, N3, [3]]
+        """
+    )
+
+
+def test_call_verb_not_found(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let o = create(N0, N0)
+        $ ;o.foo()
+        !! E_VERBNF
+        $ ;add_verb(o, [N1, "r", "foo"], ["this", "none", "this"])
+        $ ;o.foo()
+        !! E_VERBNF
+        $ ;toobj(get_highest_object_number() + 1).foo()
+        !! E_INVIND
+        """
+    )
+
+
+def test_pass(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let a = create(N0, N0)
+        $ ;let b = create(a, N0)
+        $ ;let c = create(b, N0)
+        $ ;add_verb(a, [N1, "rx", "foo"], ["this", "none", "this"])
+        $ ;set_verb_code(a, "foo", "[\\"a\\", this, args]")
+        => []
+        $ ;add_verb(b, [N1, "rx", "foo"], ["this", "none", "this"])
+        $ ;set_verb_code(b, "foo", "[\\"b\\", pass_args(args)]")
+        => []
+        $ ;add_verb(c, [N1, "rx", "foo"], ["this", "none", "this"])
+        $ ;set_verb_code(c, "foo", "[\\"c\\", pass()]")
+        => []
+        $ ;b.foo(1)
+        => ["b", ["a", N3, [1]]]
+        $ ;c.foo(1)
+        => ["c", ["b", ["a", N4, []]]]
+        """
+    )
+
+
+def test_pass_arguments(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let a = create(N0, N0)
+        $ ;let b = create(a, N0)
+        $ ;add_verb(a, [N1, "rx", "foo"], ["this", "none", "this"])
+        $ ;set_verb_code(a, "foo", "args")
+        => []
+        $ ;add_verb(b, [N1, "rx", "foo"], ["this", "none", "this"])
+        $ ;set_verb_code(b, "foo", "[pass(1, 2), pass(), pass(args), pass_args(args)]")
+        => []
+        $ ;b.foo(3, 4)
+        => [[1, 2], [], [[3, 4]], [3, 4]]
+        """
+    )
+
+
+def test_pass_no_parent_verb(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let o = create(N0, N0)
+        $ ;add_verb(o, [N1, "rx", "foo"], ["this", "none", "this"])
+        $ ;set_verb_code(o, "foo", "pass(args)")
+        => []
+        $ ;o.foo()
+        !! E_VERBNF
+        """
+    )
+
+
+def test_max_recursion(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let o = create(N0, N0)
+        $ ;add_verb(o, [N1, "rx", "rec"], ["this", "none", "this"])
+        $ ;set_verb_code(o, "rec", "if args[0] == 0 { return 0; } this.rec(args[0] - 1) + 1")
+        => []
+        $ ;o.rec(49)
+        => 49
+        $ ;o.rec(50)
+        !! E_MAXREC
+        """
+    )
+
+
+def test_callers(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let o = create(N0, N0)
+        $ ;add_verb(o, [N1, "rx", "outer"], ["this", "none", "this"])
+        $ ;set_verb_code(o, "outer", "this.inner()")
+        => []
+        $ ;add_verb(o, [N0, "rx", "inner"], ["this", "none", "this"])
+        $ ;set_verb_code(o, "inner", "[callers(), caller_perms()]")
+        => []
+        $ ;o.outer()
+        => [[[N2, "outer", N1, N2, N1]], N1]
+        $ ;callers()
+        => []
+        $ ;caller_perms()
+        => N-1
+        """
+    )
+
+
+def test_verb_cache(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let o = create(N0, N0)
+        $ ;add_verb(o, [N1, "rx", "foo"], ["this", "none", "this"])
+        $ ;set_verb_code(o, "foo", "1")
+        => []
+        $ ;let before = verb_cache_stats(); o.foo(); o.foo(); let after = verb_cache_stats(); after[0] - before[0]
+        => 1
+        $ ;set_verb_code(o, "foo", "2")
+        => []
+        $ ;o.foo()
+        => 2
+        $ ;let size = verb_cache_stats()[2]; set_verb_code(o, "foo", "3"); verb_cache_stats()[2] - size
+        => -1
+        """
+    )
+
+
+def test_verb_cache_stats_not_allowed(connect: Connect) -> None:
+    connect().cram(
+        """
+        $ ;let p = create(N0, N0); set_task_perms(p); verb_cache_stats()
+        !! E_PERM
+        """
+    )
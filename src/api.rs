@@ -1,28 +1,41 @@
 use std::{
+    collections::HashSet,
     convert::{TryFrom, TryInto},
     str::FromStr,
+    time::{Duration, Instant},
 };
 
 use rand::Rng;
 use rhai::Array;
-use rhai::{Dynamic, Engine};
+use rhai::{
+    CallFnOptions, Dynamic, Engine, EvalAltResult, FnPtr, NativeCallContext, ParseError, Scope, AST,
+};
 use sha2::{Digest, Sha512};
 use strum::EnumMessage;
 
 use crate::{
-    database::{PropertyInfo, PropertyPerms, SharedDatabase, ID},
+    connections::SharedConnections,
+    crypt,
+    database::{
+        ArgSpec, PrepSpec, PropertyInfo, PropertyPerms, SharedDatabase, Verb, VerbArgs, VerbDesc,
+        VerbInfo, VerbPerms, ID, PREPOSITIONS,
+    },
     error::{
         Error::{self, *},
         RhaiError, RhaiResult,
     },
-    task_context::TASK_CONTEXT,
+    matching, scheduler,
+    task_context::{Frame, TASK_CONTEXT},
+    verb_cache,
 };
 
 macro_rules! api_functions {
-    ($db_in:ident, $db_out:ident, $engine:ident, { $(fn $name:ident($($args:tt)*) -> $r:ty $b:block)* }) => {
+    ($db_in:ident, $db_out:ident, $conns_in:ident, $conns_out:ident, $engine:ident, $names:ident, { $(fn $name:ident($($args:tt)*) -> $r:ty $b:block)* }) => {
         $(
             let $db_out = $db_in.clone();
-            $engine.register_result_fn(stringify!($name), move |$($args)*| -> RhaiResult<$r> { $b });
+            let $conns_out = $conns_in.clone();
+            $engine.register_fn(stringify!($name), move |$($args)*| -> RhaiResult<$r> { $b });
+            $names.insert(stringify!($name));
         )*
     };
 }
@@ -39,8 +52,9 @@ macro_rules! register_operators {
 }
 
 #[allow(unused_variables)]
-pub fn register_api(engine: &mut Engine, database: SharedDatabase) {
-    api_functions!(database, db, engine, {
+pub fn register_api(engine: &mut Engine, database: SharedDatabase, connections: SharedConnections) {
+    let mut builtins = HashSet::new();
+    api_functions!(database, db, connections, conns, engine, builtins, {
         // Non-MOO / testing functions
         fn get_highest_object_number() -> ID {
             Ok(db.read().get_highest_object_number())
@@ -125,6 +139,18 @@ pub fn register_api(engine: &mut Engine, database: SharedDatabase) {
 
         // string_hash broken out to be used in value_hash
 
+        fn crypt(text: &str, salt: &str) -> String {
+            match crypt::crypt(text, salt) {
+                None => bail!(E_INVARG),
+                Some(hash) => Ok(hash),
+            }
+        }
+
+        fn crypt(text: &str) -> String {
+            // SHA-512 with a random salt
+            Ok(crypt::crypt(text, "$6$").unwrap())
+        }
+
         // Fundamental Operations on Objects
         // https://www.sindome.org/moo-manual.html#fundamental-operations-on-objects
 
@@ -148,6 +174,43 @@ pub fn register_api(engine: &mut Engine, database: SharedDatabase) {
             Ok(O::new(db.read().parent(o.id)))
         }
 
+        fn children(o: O) -> Array {
+            let db = db.read();
+            if !db.valid(o.id) {
+                bail!(E_INVARG);
+            }
+            Ok(db
+                .children(o.id)
+                .iter()
+                .map(|&id| Dynamic::from(O::new(id)))
+                .collect())
+        }
+
+        // Closest first
+        fn ancestors(o: O) -> Array {
+            let db = db.read();
+            if !db.valid(o.id) {
+                bail!(E_INVARG);
+            }
+            Ok(db
+                .ancestors(o.id)
+                .into_iter()
+                .map(|id| Dynamic::from(O::new(id)))
+                .collect())
+        }
+
+        fn descendants(o: O) -> Array {
+            let db = db.read();
+            if !db.valid(o.id) {
+                bail!(E_INVARG);
+            }
+            Ok(db
+                .descendants(o.id)
+                .into_iter()
+                .map(|id| Dynamic::from(O::new(id)))
+                .collect())
+        }
+
         fn chparent(o: O, parent: O) -> () {
             TASK_CONTEXT.with(|context| {
                 db.write()
@@ -159,12 +222,35 @@ pub fn register_api(engine: &mut Engine, database: SharedDatabase) {
             Ok(db.read().valid(obj.id))
         }
 
+        fn is_player(obj: O) -> bool {
+            let lock = db.read();
+            if !lock.valid(obj.id) {
+                bail!(E_INVARG);
+            }
+            Ok(lock.is_player(obj.id))
+        }
+
+        fn set_player_flag(obj: O, value: bool) -> () {
+            TASK_CONTEXT.with(|context| {
+                db.write()
+                    .set_player_flag(obj.id, value, context.read().task_perms)
+            })
+        }
+
+        // Matches an object in the player's inventory or location, like the command parser does.
+        // Returns Cfailed_match or Cambiguous_match if there's not exactly one match.
+        fn match_object(name: &str) -> O {
+            let player = TASK_CONTEXT.with(|context| context.read().connected_player);
+            Ok(O::new(matching::match_object(&db.read(), name, player)))
+        }
+
         // Operations on Properties
         // https://www.sindome.org/moo-manual.html#operations-on-properties
 
         fn add_property(obj: O, name: &str, value: Dynamic, info: Array) -> () {
+            let programmer = TASK_CONTEXT.with(|context| context.read().task_perms);
             db.write()
-                .add_property(obj.id, name, value, info.try_into()?)
+                .add_property(obj.id, name, value, info.try_into()?, programmer)
         }
 
         fn property_info(obj: O, name: &str) -> Array {
@@ -176,6 +262,204 @@ pub fn register_api(engine: &mut Engine, database: SharedDatabase) {
             ])
         }
 
+        // info is [owner, perms] or [owner, perms, new-name]
+        fn set_property_info(obj: O, name: &str, info: Array) -> () {
+            let programmer = TASK_CONTEXT.with(|context| context.read().task_perms);
+            db.write()
+                .set_property_info(obj.id, name, info.try_into()?, programmer)
+        }
+
+        fn delete_property(obj: O, name: &str) -> () {
+            let programmer = TASK_CONTEXT.with(|context| context.read().task_perms);
+            db.write().delete_property(obj.id, name, programmer)
+        }
+
+        // Only the properties defined on obj itself, not the inherited ones
+        fn properties(obj: O) -> Array {
+            let programmer = TASK_CONTEXT.with(|context| context.read().task_perms);
+            let names = db.read().properties(obj.id, programmer)?;
+            Ok(names.into_iter().map(Dynamic::from).collect())
+        }
+
+        fn is_clear_property(obj: O, name: &str) -> bool {
+            let programmer = TASK_CONTEXT.with(|context| context.read().task_perms);
+            db.read().is_clear_property(obj.id, name, programmer)
+        }
+
+        fn clear_property(obj: O, name: &str) -> () {
+            let programmer = TASK_CONTEXT.with(|context| context.read().task_perms);
+            db.write().clear_property(obj.id, name, programmer)
+        }
+
+        // Operations on Verbs
+        // https://www.sindome.org/moo-manual.html#operations-on-verbs
+
+        fn add_verb(obj: O, info: Array, args: Array) -> () {
+            TASK_CONTEXT.with(|context| {
+                db.write().add_verb(
+                    obj.id,
+                    info.try_into()?,
+                    args.try_into()?,
+                    context.read().task_perms,
+                )
+            })
+        }
+
+        fn delete_verb(obj: O, desc: Dynamic) -> () {
+            TASK_CONTEXT.with(|context| {
+                let context = context.read();
+                db.write()
+                    .delete_verb(obj.id, &desc.try_into()?, context.task_perms)?;
+                context.verb_cache.lock().invalidate(obj.id);
+                Ok(())
+            })
+        }
+
+        fn verb_code(obj: O, desc: Dynamic) -> Array {
+            TASK_CONTEXT.with(|context| {
+                let lock = db.read();
+                let code = lock.verb_code(obj.id, &desc.try_into()?, context.read().task_perms)?;
+                Ok(code.lines().map(|l| Dynamic::from(l.to_string())).collect())
+            })
+        }
+
+        fn set_verb_code(ctx: NativeCallContext, obj: O, desc: Dynamic, code: Dynamic) -> Array {
+            let code = if code.is::<String>() {
+                code.into_string()?
+            } else if code.is::<Array>() {
+                code.cast::<Array>()
+                    .into_iter()
+                    .map(|line| line.into_string().map_err(|_| E_TYPE.into()))
+                    .collect::<RhaiResult<Vec<String>>>()?
+                    .join("\n")
+            } else {
+                bail!(E_TYPE)
+            };
+
+            // Like in MOO, code that doesn't compile is reported back and not stored
+            if let Err(e) = compile_verb(ctx.engine(), &code) {
+                return Ok(vec![Dynamic::from(e.to_string())]);
+            }
+
+            TASK_CONTEXT.with(|context| {
+                let context = context.read();
+                db.write()
+                    .set_verb_code(obj.id, &desc.try_into()?, code, context.task_perms)?;
+                context.verb_cache.lock().invalidate(obj.id);
+                Ok(Array::new())
+            })
+        }
+
+        // Asks to:accept(what) first, which only wizards may ignore, then calls exitfunc(what) on
+        // the old location and enterfunc(what) on the new one, if they have them
+        fn move(ctx: NativeCallContext, what: O, to: O) -> () {
+            let programmer = TASK_CONTEXT.with(|context| context.read().task_perms);
+            db.read().check_move(what.id, to.id, programmer)?;
+            if to.id != -1 {
+                // Without an accept verb, nothing is accepted
+                let has_accept = db.read().find_callable_verb(to.id, "accept").is_ok();
+                let args = vec![Dynamic::from(O::new(what.id))];
+                let accepted = has_accept
+                    && is_true(&call_verb(ctx.engine(), &db, to.id, "accept", args)?);
+                if !accepted && !db.read().is_wizard(programmer) {
+                    bail!(E_NACC);
+                }
+            }
+
+            let from = db.write().move_object(what.id, to.id)?;
+            for (hook, on) in [("exitfunc", from), ("enterfunc", to.id)] {
+                if db.read().find_callable_verb(on, hook).is_ok() {
+                    let args = vec![Dynamic::from(O::new(what.id))];
+                    let _ = call_verb(ctx.engine(), &db, on, hook, args)?;
+                }
+            }
+            Ok(())
+        }
+
+        // pass() itself takes any number of arguments, so it's handled in on_missing_function below.
+        // pass_args(args) is the equivalent of pass(@args) in MOO.
+        fn pass_args(ctx: NativeCallContext, args: Array) -> Dynamic {
+            call_parent_verb(ctx.engine(), &db, args)
+        }
+
+        // Operations on Network Connections
+        // https://www.sindome.org/moo-manual.html#operations-on-network-connections
+
+        // Does nothing if the player isn't connected
+        fn notify(player: O, text: &str) -> () {
+            let programmer = TASK_CONTEXT.with(|context| context.read().task_perms);
+            if programmer != player.id && !db.read().is_wizard(programmer) {
+                bail!(E_PERM);
+            }
+            if let Some(connection) = conns.read().get(player.id) {
+                connection.send_line(text);
+            }
+            Ok(())
+        }
+
+        // Closes the player's connection right away, once the output sent so far is written, which
+        // calls #0:user_disconnected. Does nothing if the player isn't connected.
+        fn boot_player(player: O) -> () {
+            let programmer = TASK_CONTEXT.with(|context| context.read().task_perms);
+            if programmer != player.id && !db.read().is_wizard(programmer) {
+                bail!(E_PERM);
+            }
+            if let Some(connection) = conns.read().get(player.id) {
+                connection.send_line("*** Disconnected ***");
+                connection.close();
+            }
+            Ok(())
+        }
+
+        // Only wizards may list connections that haven't logged in yet
+        fn connected_players(include_all: bool) -> Array {
+            if include_all {
+                let programmer = TASK_CONTEXT.with(|context| context.read().task_perms);
+                if !db.read().is_wizard(programmer) {
+                    bail!(E_PERM);
+                }
+            }
+            Ok(conns
+                .read()
+                .players(include_all)
+                .into_iter()
+                .map(|id| Dynamic::from(O::new(id)))
+                .collect())
+        }
+        fn connected_players() -> Array {
+            Ok(conns
+                .read()
+                .players(false)
+                .into_iter()
+                .map(|id| Dynamic::from(O::new(id)))
+                .collect())
+        }
+
+        fn connected_seconds(player: O) -> rhai::INT {
+            match conns.read().get(player.id) {
+                None => bail!(E_INVARG),
+                Some(connection) => Ok(connection.connected_at.elapsed().as_secs() as rhai::INT),
+            }
+        }
+
+        fn idle_seconds(player: O) -> rhai::INT {
+            match conns.read().get(player.id) {
+                None => bail!(E_INVARG),
+                Some(connection) => Ok(connection.last_input_at().elapsed().as_secs() as rhai::INT),
+            }
+        }
+
+        fn connection_name(player: O) -> String {
+            let programmer = TASK_CONTEXT.with(|context| context.read().task_perms);
+            if programmer != player.id && !db.read().is_wizard(programmer) {
+                bail!(E_PERM);
+            }
+            match conns.read().get(player.id) {
+                None => bail!(E_INVARG),
+                Some(connection) => Ok(connection.name.clone()),
+            }
+        }
+
         // Operations on Numbers
         // https://www.sindome.org/moo-manual.html#operations-on-numbers
 
@@ -251,37 +535,182 @@ pub fn register_api(engine: &mut Engine, database: SharedDatabase) {
         // Built-in Functions / Manipulating Objects / MOO-Code Evaluation and Task Manipulation
         // https://www.sindome.org/moo-manual.html#moo-code-evaluation-and-task-management
 
+        // Suspends the task until the player types a line, which isn't parsed as a command.
+        // Only reads from the connection the task was started from.
+        fn read() -> String {
+            // Queue up before the connection moves on, so that a line sent right away isn't taken
+            // for a command. The connection then hands the next line over, instead of waiting
+            // for the task.
+            let reader = TASK_CONTEXT.with(|context| {
+                let mut context = context.write();
+                let reader = context
+                    .connection
+                    .clone()
+                    .and_then(|connection| Some((connection.register_reader()?, connection)));
+                context.detach();
+                reader
+            });
+            let line = reader.and_then(|(line, connection)| connection.wait_for_line(line));
+            // Waiting for the player doesn't count against the task's budget
+            TASK_CONTEXT.with(|context| context.write().reset_limits(false));
+            match line {
+                None => bail!(E_INVARG),
+                Some(line) => Ok(line),
+            }
+        }
+
+        // Calls f (a closure) after the given number of seconds, in a new task.
+        // Returns the ID of the new task.
+        fn fork(ctx: NativeCallContext, seconds: rhai::INT, f: FnPtr) -> rhai::INT {
+            if seconds < 0 {
+                bail!(E_INVARG);
+            }
+            scheduler::fork(&ctx, Duration::from_secs(seconds as u64), f)
+        }
+
+        // Suspends the task until it's resumed or killed by another task
+        fn suspend() -> Dynamic {
+            scheduler::suspend(None)
+        }
+
+        // Suspends the task for the given number of seconds, unless it's resumed or killed first.
+        // Returns the value passed to resume(), or 0 if the time ran out.
+        fn suspend(seconds: rhai::INT) -> Dynamic {
+            if seconds < 0 {
+                bail!(E_INVARG);
+            }
+            scheduler::suspend(Some(Duration::from_secs(seconds as u64)))
+        }
+
+        fn resume(task_id: rhai::INT) -> () {
+            resume_task(&db, task_id, Dynamic::ZERO)
+        }
+
+        // Makes suspend() return value in the suspended task task_id
+        fn resume(task_id: rhai::INT, value: Dynamic) -> () {
+            resume_task(&db, task_id, value)
+        }
+
+        // Removes a queued or suspended task. Killing the current task stops it right away.
+        fn kill_task(task_id: rhai::INT) -> () {
+            let (current, programmer, scheduler) = TASK_CONTEXT.with(|context| {
+                let context = context.read();
+                (
+                    context.task_id,
+                    context.task_perms,
+                    context.scheduler.clone(),
+                )
+            });
+            if task_id == current {
+                return Err(scheduler::killed());
+            }
+            let owner = match scheduler.read().get(task_id) {
+                None => bail!(E_INVARG),
+                Some(task) => task.programmer,
+            };
+            if programmer != owner && !db.read().is_wizard(programmer) {
+                bail!(E_PERM);
+            }
+            scheduler.write().kill(task_id);
+            Ok(())
+        }
+
+        // Operations the task may still run before it's aborted
+        fn ticks_left() -> rhai::INT {
+            Ok(TASK_CONTEXT.with(|context| context.read().ticks_left))
+        }
+
+        fn seconds_left() -> rhai::INT {
+            let deadline = TASK_CONTEXT.with(|context| context.read().deadline);
+            Ok(deadline.saturating_duration_since(Instant::now()).as_secs() as rhai::INT)
+        }
+
+        fn task_id() -> rhai::INT {
+            Ok(TASK_CONTEXT.with(|context| context.read().task_id))
+        }
+
+        // Tasks waiting to run with the current permissions, or all of them for wizards
+        fn queued_tasks() -> Array {
+            let (programmer, scheduler) = TASK_CONTEXT.with(|context| {
+                let context = context.read();
+                (context.task_perms, context.scheduler.clone())
+            });
+            let all = db.read().is_wizard(programmer);
+            let tasks = scheduler
+                .read()
+                .list(programmer, all)
+                .into_iter()
+                .map(|task| Dynamic::from(task.to_array()))
+                .collect();
+            Ok(tasks)
+        }
+
+        // [hits, misses, entries] of the cache of compiled verbs and ; lines. Only for wizards.
+        fn verb_cache_stats() -> Array {
+            let (programmer, verb_cache) = TASK_CONTEXT.with(|context| {
+                let context = context.read();
+                (context.task_perms, context.verb_cache.clone())
+            });
+            if !db.read().is_wizard(programmer) {
+                bail!(E_PERM);
+            }
+            let (hits, misses, size) = verb_cache.lock().stats();
+            Ok(vec![
+                Dynamic::from(hits as rhai::INT),
+                Dynamic::from(misses as rhai::INT),
+                Dynamic::from(size as rhai::INT),
+            ])
+        }
+
         fn set_task_perms(who: O) -> () {
             TASK_CONTEXT.with(|context| {
-                context.write().task_perms = who.id;
+                let mut context = context.write();
+                context.task_perms = who.id;
+                if let Some(frame) = context.frames.last_mut() {
+                    frame.programmer = who.id;
+                }
             });
             Ok(())
         }
-    });
 
-    // toliteral is recursive, so we need a standalone function definition first
-    fn toliteral(x: Dynamic) -> RhaiResult<String> {
-        Ok(if x.is::<O>() {
-            format!("N{}", x.cast::<O>().id)
-        } else if x.is::<String>() {
-            format!("{:?}", x.cast::<String>())
-        } else if x.is::<Error>() {
-            format!("{}", x.cast::<Error>())
-        } else if x.is::<Array>() {
-            format!(
-                "[{}]",
-                x.cast::<Array>()
+        fn caller_perms() -> O {
+            TASK_CONTEXT.with(|context| {
+                Ok(O::new(
+                    context
+                        .read()
+                        .frames
+                        .last()
+                        .map(|frame| frame.caller_perms)
+                        .unwrap_or(-1),
+                ))
+            })
+        }
+
+        // [this, verb name, programmer, verb location, player] for each verb that led to the current one,
+        // innermost first. Unlike in MOO, there are no line numbers.
+        fn callers() -> Array {
+            TASK_CONTEXT.with(|context| {
+                let context = context.read();
+                Ok(context
+                    .frames
                     .iter()
-                    .cloned()
-                    .map(toliteral)
-                    .collect::<RhaiResult<Vec<String>>>()?
-                    .join(", "),
-            )
-        } else {
-            x.to_string()
-        })
-    }
-    engine.register_result_fn("toliteral", toliteral);
+                    .rev()
+                    .skip(1)
+                    .map(|frame| {
+                        Dynamic::from(vec![
+                            Dynamic::from(O::new(frame.this)),
+                            Dynamic::from(frame.verb.clone()),
+                            Dynamic::from(O::new(frame.programmer)),
+                            Dynamic::from(O::new(frame.definer)),
+                            Dynamic::from(O::new(frame.player)),
+                        ])
+                    })
+                    .collect())
+            })
+        }
+    });
+
+    engine.register_fn("toliteral", toliteral);
 
     fn str_tofloat(s: &str) -> RhaiResult<rhai::FLOAT> {
         Ok(s.split_whitespace()
@@ -289,34 +718,34 @@ pub fn register_api(engine: &mut Engine, database: SharedDatabase) {
             .parse::<rhai::FLOAT>()
             .unwrap_or(0.0))
     }
-    engine.register_result_fn("tofloat", str_tofloat);
+    engine.register_fn("tofloat", str_tofloat);
 
     // toint implementations, broken out into actual functions
     // so that they can be used in toobj
     fn int_toint(i: rhai::INT) -> RhaiResult<rhai::INT> {
         Ok(i)
     }
-    engine.register_result_fn("toint", int_toint);
+    engine.register_fn("toint", int_toint);
 
     fn float_toint(f: rhai::FLOAT) -> RhaiResult<rhai::INT> {
         Ok(f as rhai::INT)
     }
-    engine.register_result_fn("toint", float_toint);
+    engine.register_fn("toint", float_toint);
 
     fn object_toint(o: O) -> RhaiResult<rhai::INT> {
         Ok(o.id)
     }
-    engine.register_result_fn("toint", object_toint);
+    engine.register_fn("toint", object_toint);
 
     fn str_toint(s: &str) -> RhaiResult<rhai::INT> {
         str_tofloat(s).map(|f| f as rhai::INT)
     }
-    engine.register_result_fn("toint", str_toint);
+    engine.register_fn("toint", str_toint);
 
     fn dynamic_toint(d: Dynamic) -> RhaiResult<rhai::INT> {
         bail!(E_TYPE)
     }
-    engine.register_result_fn("toint", dynamic_toint);
+    engine.register_fn("toint", dynamic_toint);
 
     // Failed attempt for #0 object notation: custom syntax
     // Problem 1: Rhai refuses # as the first token of a custom syntax tree
@@ -345,7 +774,7 @@ pub fn register_api(engine: &mut Engine, database: SharedDatabase) {
         let result = hasher.finalize();
         Ok(format!("{:x}", result))
     }
-    engine.register_result_fn("string_hash", string_hash);
+    engine.register_fn("string_hash", string_hash);
 
     // Errors
     engine
@@ -356,6 +785,7 @@ pub fn register_api(engine: &mut Engine, database: SharedDatabase) {
 
     // Custom variable resolvers
     let db = database.clone();
+    #[allow(deprecated)] // on_var is stable in practice, just marked volatile
     engine.on_var(move |name, _, context| {
         // Error constants (like E_INVARG)
         if let Ok(e) = Error::from_str(name) {
@@ -372,11 +802,38 @@ pub fn register_api(engine: &mut Engine, database: SharedDatabase) {
         */
         // Cnothing corified notation (like $nothing in Moo)
         if let Some(prop) = name.strip_prefix('C') {
-            return db.read().get_property_dynamic(0, prop).map(Some);
+            let programmer = TASK_CONTEXT.with(|context| context.read().task_perms);
+            return db
+                .read()
+                .get_property_dynamic(0, prop, programmer)
+                .map(Some);
         }
         Ok(None)
     });
 
+    // Verb calls: o.verb(args) calls the verb unless there's a builtin function with the same name.
+    // Rhai also invokes this callback when a builtin fails, hence the check against `builtins`.
+    let db = database.clone();
+    #[allow(deprecated)] // on_missing_function is stable in practice, just marked volatile
+    engine.on_missing_function(move |name, args, is_method_call, context| {
+        // pass(...) calls the current verb's implementation on the parent of the object defining it,
+        // with the given arguments. No registered function could take any number of them.
+        if !is_method_call && name == "pass" {
+            let args = args.iter().map(|a| (**a).clone()).collect();
+            return call_parent_verb(context.engine(), &db, args).map(Some);
+        }
+        // Property getters / setters / indexers also end up here, those are never verbs
+        if !is_method_call || builtins.contains(name) || name.contains('$') {
+            return Ok(None);
+        }
+        let this = match args[0].clone().try_cast::<O>() {
+            None => return Ok(None),
+            Some(o) => o.id,
+        };
+        let args = args[1..].iter().map(|a| (**a).clone()).collect();
+        call_verb(context.engine(), &db, this, name, args).map(Some)
+    });
+
     // ObjectProxy
     engine.register_type_with_name::<O>("Object");
 
@@ -384,28 +841,58 @@ pub fn register_api(engine: &mut Engine, database: SharedDatabase) {
 
     // built-in property: name
     let db = database.clone();
-    engine.register_get_result("name", move |o: &mut O| db.read().get_name(o.id));
+    engine.register_get("name", move |o: &mut O| db.read().get_name(o.id));
     let db = database.clone();
-    engine.register_set_result("name", move |o: &mut O, name: &str| {
+    engine.register_set("name", move |o: &mut O, name: &str| {
         db.write().set_name(o.id, name)
     });
 
     // built-in property: f
     let db = database.clone();
-    engine.register_get_result("f", move |o: &mut O| db.read().is_fertile(o.id));
+    engine.register_get("f", move |o: &mut O| db.read().is_fertile(o.id));
     let db = database.clone();
-    engine.register_set_result("f", move |o: &mut O, f: bool| {
+    engine.register_set("f", move |o: &mut O, f: bool| {
         db.write().set_fertile(o.id, f)
     });
 
+    // built-in properties: location and contents, which only move() changes
+    let db = database.clone();
+    engine.register_get("location", move |o: &mut O| -> RhaiResult<O> {
+        let db = db.read();
+        if !db.valid(o.id) {
+            bail!(E_INVIND);
+        }
+        Ok(O::new(db.location(o.id)))
+    });
+    engine.register_set("location", |_: &mut O, _: O| -> RhaiResult<()> {
+        bail!(E_PERM)
+    });
+    let db = database.clone();
+    engine.register_get("contents", move |o: &mut O| -> RhaiResult<Array> {
+        let db = db.read();
+        if !db.valid(o.id) {
+            bail!(E_INVIND);
+        }
+        Ok(db
+            .contents(o.id)
+            .iter()
+            .map(|&id| Dynamic::from(O::new(id)))
+            .collect())
+    });
+    engine.register_set("contents", |_: &mut O, _: Array| -> RhaiResult<()> {
+        bail!(E_PERM)
+    });
+
     // non-built-in properties
     let db = database.clone();
-    engine.register_indexer_get_result(move |o: &mut O, prop: &str| {
-        db.read().get_property_dynamic(o.id, prop)
+    engine.register_indexer_get(move |o: &mut O, prop: &str| {
+        let programmer = TASK_CONTEXT.with(|context| context.read().task_perms);
+        db.read().get_property_dynamic(o.id, prop, programmer)
     });
     let db = database.clone();
-    engine.register_indexer_set_result(move |o: &mut O, prop: &str, val: Dynamic| {
-        db.write().set_property_dynamic(o.id, prop, val)
+    engine.register_indexer_set(move |o: &mut O, prop: &str, val: Dynamic| {
+        let programmer = TASK_CONTEXT.with(|context| context.read().task_perms);
+        db.write().set_property_dynamic(o.id, prop, val, programmer)
     });
 
     // Other helper functions
@@ -420,9 +907,9 @@ pub fn register_api(engine: &mut Engine, database: SharedDatabase) {
     engine
         .register_custom_operator("lets", 20)
         .unwrap()
-        .register_custom_syntax_raw(
+        .register_custom_syntax_with_state_raw(
             "lets",
-            |symbols, look_ahead| {
+            |symbols, look_ahead, _state| {
                 // lets ...
                 if symbols.len() == 1 {
                     return Ok(Some("[".into()));
@@ -461,7 +948,7 @@ pub fn register_api(engine: &mut Engine, database: SharedDatabase) {
                 Ok(None)
             },
             true,
-            |context, inputs| -> RhaiResult<Dynamic> {
+            |context, inputs, _state| -> RhaiResult<Dynamic> {
                 #[derive(Debug)]
                 struct Var {
                     name: String,
@@ -474,7 +961,7 @@ pub fn register_api(engine: &mut Engine, database: SharedDatabase) {
                 let mut vars: Vec<Var> = vec![];
                 let mut rest: Option<String> = None;
                 for input in &inputs[..inputs.len() - 1] {
-                    if let Some(var) = input.get_variable_name() {
+                    if let Some(var) = input.get_string_value() {
                         if let Some(stripped) = var.strip_prefix("OPT_") {
                             // OPT_variable, same as ?variable in Moo
                             vars.push(Var {
@@ -570,6 +1057,247 @@ pub fn register_api(engine: &mut Engine, database: SharedDatabase) {
         );
 }
 
+/// The MOO literal for `x`, also used to show the results of `;` lines
+pub fn toliteral(x: Dynamic) -> RhaiResult<String> {
+    Ok(if x.is::<O>() {
+        format!("N{}", x.cast::<O>().id)
+    } else if x.is::<String>() {
+        format!("{:?}", x.cast::<String>())
+    } else if x.is::<Error>() {
+        format!("{}", x.cast::<Error>())
+    } else if x.is::<Array>() {
+        format!(
+            "[{}]",
+            x.cast::<Array>()
+                .iter()
+                .cloned()
+                .map(toliteral)
+                .collect::<RhaiResult<Vec<String>>>()?
+                .join(", "),
+        )
+    } else {
+        x.to_string()
+    })
+}
+
+/// Name of the function that verb code is wrapped into.
+///
+/// Rhai only allows `this` inside functions, so verbs are compiled as the body of a
+/// function, and the verb context (`player`, `caller`, ...) is passed in as arguments.
+pub const VERB_FN_NAME: &str = "verb_body";
+const VERB_FN_PARAMS: &str =
+    "player, caller, verb, args, argstr, dobj, dobjstr, prepstr, iobj, iobjstr";
+
+pub fn compile_verb(engine: &Engine, code: &str) -> Result<AST, ParseError> {
+    // No newline after the opening brace, so that line numbers in errors match the verb code
+    engine
+        .compile(format!("{}{}\n}}", verb_fn_header(), code))
+        .map_err(|ParseError(e, pos)| ParseError(e, verb_code_position(pos)))
+}
+
+fn verb_fn_header() -> String {
+    format!("fn {}({}) {{ ", VERB_FN_NAME, VERB_FN_PARAMS)
+}
+
+/// Translates a position in the wrapped verb function to a position in the verb code
+fn verb_code_position(pos: rhai::Position) -> rhai::Position {
+    let header_len = verb_fn_header().len();
+    match (pos.line(), pos.position()) {
+        (Some(1), Some(col)) if col > header_len => {
+            rhai::Position::new(1, (col - header_len) as u16)
+        }
+        _ => pos,
+    }
+}
+
+/// Calls the verb `name` on `this`, looking it up on `this` and then on its ancestors.
+///
+/// The verb runs with the permissions of its owner, with `this` bound to `this`, and
+/// `player`, `caller`, `verb` and `args` passed in as arguments.
+pub fn call_verb(
+    engine: &Engine,
+    database: &SharedDatabase,
+    this: ID,
+    name: &str,
+    args: Array,
+) -> RhaiResult<Dynamic> {
+    run_verb(engine, database, this, this, name, args)
+}
+
+/// Calls the verb `name` with `this` bound to `this`, but starts looking for it on `lookup_start`.
+/// Used directly by `pass`, which needs to skip the verb that's currently running.
+fn run_verb(
+    engine: &Engine,
+    database: &SharedDatabase,
+    this: ID,
+    lookup_start: ID,
+    name: &str,
+    args: Array,
+) -> RhaiResult<Dynamic> {
+    let (definer, verb) = {
+        let db = database.read();
+        let (definer, verb) = db.find_callable_verb(lookup_start, name)?;
+        (definer, verb.clone())
+    };
+    execute_verb(engine, this, definer, &verb, name, args)
+}
+
+/// Runs `verb` (defined on `definer`) with `this` bound to `this`, and the verb called `name`.
+pub fn execute_verb(
+    engine: &Engine,
+    this: ID,
+    definer: ID,
+    verb: &Verb,
+    name: &str,
+    args: Array,
+) -> RhaiResult<Dynamic> {
+    let owner = verb.info().owner;
+    let verb_cache = TASK_CONTEXT.with(|context| context.read().verb_cache.clone());
+    let ast = verb_cache::verb(
+        &verb_cache,
+        definer,
+        &verb.info().names,
+        verb.code(),
+        |code| compile_verb(engine, code),
+    )?;
+
+    let (player, caller, command) = TASK_CONTEXT.with(|context| -> RhaiResult<_> {
+        let mut context = context.write();
+        if context.frames.len() >= context.max_stack_depth {
+            bail!(E_MAXREC);
+        }
+        let player = context.connected_player;
+        let caller = context
+            .frames
+            .last()
+            .map(|frame| frame.this)
+            .unwrap_or(player);
+        let caller_perms = std::mem::replace(&mut context.task_perms, owner);
+        context.frames.push(Frame {
+            this,
+            verb: name.to_string(),
+            programmer: owner,
+            caller_perms,
+            definer,
+            player,
+        });
+        Ok((player, caller, context.command.clone()))
+    })?;
+
+    let mut this_ptr = Dynamic::from(O::new(this));
+    let result = engine.call_fn_with_options::<Dynamic>(
+        CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut this_ptr),
+        &mut Scope::new(),
+        &ast,
+        VERB_FN_NAME,
+        vec![
+            Dynamic::from(O::new(player)),
+            Dynamic::from(O::new(caller)),
+            Dynamic::from(name.to_string()),
+            Dynamic::from(args),
+            Dynamic::from(command.argstr),
+            Dynamic::from(O::new(command.dobj)),
+            Dynamic::from(command.dobjstr),
+            Dynamic::from(command.prepstr),
+            Dynamic::from(O::new(command.iobj)),
+            Dynamic::from(command.iobjstr),
+        ],
+    );
+
+    TASK_CONTEXT.with(|context| {
+        let mut context = context.write();
+        if let Some(frame) = context.frames.pop() {
+            context.task_perms = frame.caller_perms;
+        }
+    });
+
+    result.map_err(|e| verb_error(e, definer, name))
+}
+
+/// Shows where an error raised by the code of the verb `name` (defined on `definer`) happened, like
+/// a MOO traceback would.
+/// Uncatchable errors (like termination) are left alone, so they stay uncatchable.
+pub fn verb_error(mut e: Box<EvalAltResult>, definer: ID, name: &str) -> Box<EvalAltResult> {
+    if !e.is_catchable() {
+        return e;
+    }
+    translate_positions(&mut e);
+    Box::new(EvalAltResult::ErrorInFunctionCall(
+        format!("{}:{}", O::new(definer), name),
+        String::new(),
+        e,
+        rhai::Position::NONE,
+    ))
+}
+
+/// Translates the positions in an error raised by verb code to positions in the verb code, including
+/// the ones in closures it called. Errors from other verbs have already been translated.
+fn translate_positions(e: &mut EvalAltResult) {
+    e.set_position(verb_code_position(e.position()));
+    if let EvalAltResult::ErrorInFunctionCall(name, _, inner, _) = e {
+        if !name.contains(':') {
+            translate_positions(inner);
+        }
+    }
+}
+
+/// Resumes the suspended task `task_id` with `value`, if the current task has the permissions of its
+/// owner or a wizard
+fn resume_task(database: &SharedDatabase, task_id: rhai::INT, value: Dynamic) -> RhaiResult<()> {
+    let (programmer, scheduler) = TASK_CONTEXT.with(|context| {
+        let context = context.read();
+        (context.task_perms, context.scheduler.clone())
+    });
+    let owner = match scheduler.read().get(task_id) {
+        None => bail!(E_INVARG),
+        Some(task) => task.programmer,
+    };
+    if programmer != owner && !database.read().is_wizard(programmer) {
+        bail!(E_PERM);
+    }
+    // Tasks that were forked but haven't started yet can't be resumed
+    if !scheduler.write().resume(task_id, value) {
+        bail!(E_INVARG);
+    }
+    Ok(())
+}
+
+/// Whether `value` counts as true in conditions, like in MOO: non-zero numbers and non-empty strings
+/// and lists are true, everything else is false
+fn is_true(value: &Dynamic) -> bool {
+    if let Some(b) = value.clone().try_cast::<bool>() {
+        b
+    } else if let Some(n) = value.clone().try_cast::<rhai::INT>() {
+        n != 0
+    } else if let Some(f) = value.clone().try_cast::<rhai::FLOAT>() {
+        f != 0.0
+    } else if value.is::<String>() {
+        !value.clone().cast::<String>().is_empty()
+    } else if value.is::<Array>() {
+        !value.clone().cast::<Array>().is_empty()
+    } else {
+        false
+    }
+}
+
+fn call_parent_verb(
+    engine: &Engine,
+    database: &SharedDatabase,
+    args: Array,
+) -> RhaiResult<Dynamic> {
+    let frame = match TASK_CONTEXT.with(|context| context.read().frames.last().cloned()) {
+        None => bail!(E_VERBNF),
+        Some(frame) => frame,
+    };
+    let parent = database.read().parent(frame.definer);
+    if parent == -1 {
+        bail!(E_VERBNF);
+    }
+    run_verb(engine, database, frame.this, parent, &frame.verb, args)
+}
+
 #[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord)]
 pub struct ObjectProxy {
     id: ID,
@@ -583,6 +1311,11 @@ impl ObjectProxy {
     pub fn new(id: ID) -> Self {
         Self { id }
     }
+
+    #[must_use]
+    pub fn id(&self) -> ID {
+        self.id
+    }
 }
 
 impl std::fmt::Display for ObjectProxy {
@@ -610,7 +1343,7 @@ impl TryFrom<Array> for PropertyInfo {
             None => None,
             Some(d) => {
                 if d.is::<String>() {
-                    Some(d.clone().as_string()?)
+                    Some(d.clone().into_string()?)
                 } else {
                     bail!(E_INVARG)
                 }
@@ -621,6 +1354,140 @@ impl TryFrom<Array> for PropertyInfo {
     }
 }
 
+impl TryFrom<Array> for VerbInfo {
+    type Error = RhaiError;
+
+    fn try_from(value: Array) -> Result<Self, Self::Error> {
+        if value.len() != 3 {
+            bail!(E_INVARG);
+        }
+
+        let owner = match value[0].clone().try_cast::<ObjectProxy>() {
+            None => bail!(E_TYPE),
+            Some(obj) => obj.id,
+        };
+
+        if !value[1].is::<String>() || !value[2].is::<String>() {
+            bail!(E_TYPE);
+        }
+        let perms = VerbPerms::from_str(&value[1].clone().into_string()?)?;
+        let names = value[2].clone().into_string()?;
+
+        Ok(Self::new(owner, perms, names))
+    }
+}
+
+impl TryFrom<Array> for VerbArgs {
+    type Error = RhaiError;
+
+    fn try_from(value: Array) -> Result<Self, Self::Error> {
+        if value.len() != 3 {
+            bail!(E_INVARG);
+        }
+        if value.iter().any(|d| !d.is::<String>()) {
+            bail!(E_TYPE);
+        }
+        let strings: Vec<String> = value
+            .into_iter()
+            .map(|d| d.into_string().unwrap())
+            .collect();
+
+        Ok(Self::new(
+            ArgSpec::from_str(&strings[0])?,
+            PrepSpec::from_str(&strings[1])?,
+            ArgSpec::from_str(&strings[2])?,
+        ))
+    }
+}
+
+impl TryFrom<Dynamic> for VerbDesc {
+    type Error = RhaiError;
+
+    fn try_from(value: Dynamic) -> Result<Self, Self::Error> {
+        if value.is::<String>() {
+            Ok(VerbDesc::Name(value.into_string()?))
+        } else if value.is::<rhai::INT>() {
+            // Verb indices are 1-based in MOO
+            match value.as_int()? {
+                i if i < 1 => bail!(E_INVARG),
+                i => Ok(VerbDesc::Index(i as usize - 1)),
+            }
+        } else {
+            bail!(E_TYPE)
+        }
+    }
+}
+
+impl FromStr for ArgSpec {
+    type Err = RhaiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "this" => Ok(ArgSpec::This),
+            "any" => Ok(ArgSpec::Any),
+            "none" => Ok(ArgSpec::None),
+            _ => bail!(E_INVARG),
+        }
+    }
+}
+
+impl std::fmt::Display for ArgSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ArgSpec::This => "this",
+            ArgSpec::Any => "any",
+            ArgSpec::None => "none",
+        })
+    }
+}
+
+impl FromStr for PrepSpec {
+    type Err = RhaiError;
+
+    /// Accepts "any", "none", a full preposition entry ("with/using") or any of its spellings ("using")
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "any" => Ok(PrepSpec::Any),
+            "none" => Ok(PrepSpec::None),
+            _ => match PREPOSITIONS
+                .iter()
+                .position(|spellings| spellings.join("/") == s || spellings.contains(&s))
+            {
+                None => bail!(E_INVARG),
+                Some(i) => Ok(PrepSpec::Some(i)),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for PrepSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrepSpec::Any => f.write_str("any"),
+            PrepSpec::None => f.write_str("none"),
+            PrepSpec::Some(i) => f.write_str(&PREPOSITIONS[*i].join("/")),
+        }
+    }
+}
+
+impl FromStr for VerbPerms {
+    type Err = RhaiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut perms = VerbPerms::default();
+        for char in s.chars() {
+            match char {
+                'r' => perms.r = true,
+                'w' => perms.w = true,
+                'x' => perms.x = true,
+                'd' => perms.d = true,
+                _ => bail!(E_INVARG),
+            }
+        }
+        Ok(perms)
+    }
+}
+
 impl TryFrom<Dynamic> for PropertyPerms {
     type Error = RhaiError;
 
@@ -628,7 +1495,7 @@ impl TryFrom<Dynamic> for PropertyPerms {
         if !value.is::<String>() {
             bail!(E_INVARG);
         }
-        Self::from_str(&value.as_string().unwrap())
+        Self::from_str(&value.into_string().unwrap())
     }
 }
 
@@ -651,8 +1518,8 @@ impl FromStr for PropertyPerms {
     }
 }
 
-impl ToString for PropertyPerms {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for PropertyPerms {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut chars = vec![];
         if self.r {
             chars.push('r');
@@ -663,6 +1530,6 @@ impl ToString for PropertyPerms {
         if self.c {
             chars.push('c');
         }
-        chars.into_iter().collect()
+        f.write_str(&chars.into_iter().collect::<String>())
     }
 }
@@ -0,0 +1,210 @@
+// The Built-in Command Parser
+// https://www.sindome.org/moo-manual.html#the-built-in-command-parser
+
+use rhai::{Array, Dynamic, Engine};
+
+use crate::{
+    api::{self, ObjectProxy},
+    database::{Database, PrepSpec, SharedDatabase, ID, PREPOSITIONS},
+    error::RhaiResult,
+    matching::match_object,
+    task_context::TASK_CONTEXT,
+};
+
+/// A parsed command line, with its direct and indirect objects matched
+#[derive(Debug, Clone)]
+pub struct Command {
+    pub verb: String,
+    pub args: Vec<String>,
+    pub argstr: String,
+    pub dobjstr: String,
+    pub dobj: ID,
+    pub prepstr: String,
+    pub prep: PrepSpec,
+    pub iobjstr: String,
+    pub iobj: ID,
+}
+
+impl Default for Command {
+    fn default() -> Self {
+        Self {
+            verb: String::new(),
+            args: Vec::new(),
+            argstr: String::new(),
+            dobjstr: String::new(),
+            dobj: -1,
+            prepstr: String::new(),
+            prep: PrepSpec::None,
+            iobjstr: String::new(),
+            iobj: -1,
+        }
+    }
+}
+
+impl Command {
+    /// Returns `None` if the line doesn't contain any words
+    pub fn parse(line: &str, player: ID, db: &Database) -> Option<Self> {
+        // "foo is short for say foo, :foo is short for emote foo
+        let line = line.trim_start();
+        let line = if let Some(rest) = line.strip_prefix('"') {
+            format!("say {}", rest)
+        } else if let Some(rest) = line.strip_prefix(':') {
+            format!("emote {}", rest)
+        } else {
+            line.to_string()
+        };
+
+        let words = split_words(&line);
+        let (verb, verb_end) = words.first()?.clone();
+        let args: Vec<String> = words.into_iter().skip(1).map(|(word, _)| word).collect();
+        let argstr = line[verb_end..].trim_start().to_string();
+
+        let mut command = Self {
+            verb,
+            argstr,
+            ..Self::default()
+        };
+        match find_preposition(&args) {
+            None => command.dobjstr = args.join(" "),
+            Some((start, len, prep)) => {
+                command.dobjstr = args[..start].join(" ");
+                command.prepstr = args[start..start + len].join(" ");
+                command.prep = PrepSpec::Some(prep);
+                command.iobjstr = args[start + len..].join(" ");
+            }
+        }
+        command.dobj = match_object(db, &command.dobjstr, player);
+        command.iobj = match_object(db, &command.iobjstr, player);
+        command.args = args;
+
+        Some(command)
+    }
+}
+
+/// Parses and executes a command typed by the connected player.
+///
+/// The verb is looked up on the player, the player's location, the direct object and the indirect
+/// object, in this order. If none of them has a matching verb, the `huh` verb of the player's location
+/// is called instead. Returns `false` if not even that exists.
+pub fn execute(engine: &Engine, database: &SharedDatabase, line: &str) -> RhaiResult<bool> {
+    let player = TASK_CONTEXT.with(|context| context.read().connected_player);
+
+    let (command, target) = {
+        let db = database.read();
+        let command = match Command::parse(line, player, &db) {
+            None => return Ok(true),
+            Some(command) => command,
+        };
+        let location = db.location(player);
+        let target = [player, location, command.dobj, command.iobj]
+            .iter()
+            .find_map(|&o| {
+                db.find_command_verb(o, &command.verb, command.dobj, command.prep, command.iobj)
+                    .map(|(definer, verb)| (o, definer, verb.clone()))
+            })
+            .or_else(|| {
+                db.find_verb_named(location, "huh")
+                    .map(|(definer, verb)| (location, definer, verb.clone()))
+            });
+        (command, target)
+    };
+
+    let (this, definer, verb) = match target {
+        None => return Ok(false),
+        Some(target) => target,
+    };
+    let name = command.verb.clone();
+    let args: Array = command.args.iter().cloned().map(Dynamic::from).collect();
+
+    TASK_CONTEXT.with(|context| context.write().command = command);
+    let result = api::execute_verb(engine, this, definer, &verb, &name, args);
+    TASK_CONTEXT.with(|context| context.write().command = Command::default());
+
+    result.map(|_| true)
+}
+
+/// Passes a line typed on a connection that hasn't logged in yet to #0:do_login_command, with the
+/// words of the line as `args` and the whole line as `argstr`.
+/// Returns the player to log in as, if the verb returned one.
+pub fn do_login_command(
+    engine: &Engine,
+    database: &SharedDatabase,
+    line: &str,
+) -> RhaiResult<Option<ID>> {
+    let args: Array = split_words(line)
+        .into_iter()
+        .map(|(word, _)| Dynamic::from(word))
+        .collect();
+
+    TASK_CONTEXT.with(|context| {
+        context.write().command = Command {
+            argstr: line.to_string(),
+            ..Command::default()
+        }
+    });
+    let result = api::call_verb(engine, database, 0, "do_login_command", args);
+    TASK_CONTEXT.with(|context| context.write().command = Command::default());
+
+    Ok(result?
+        .try_cast::<ObjectProxy>()
+        .map(|o| o.id())
+        .filter(|&id| database.read().is_player(id)))
+}
+
+/// Splits a line into words like LambdaMOO does: words are separated by spaces, double quotes group
+/// words together, and a backslash makes the next character literal.
+///
+/// Returns each word along with the byte offset of where it ends in the line.
+fn split_words(line: &str) -> Vec<(String, usize)> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut in_quotes = false;
+    let mut chars = line.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            ' ' if !in_quotes => {
+                if in_word {
+                    words.push((std::mem::take(&mut word), i));
+                    in_word = false;
+                }
+                continue;
+            }
+            '"' => in_quotes = !in_quotes,
+            '\\' => {
+                if let Some((_, escaped)) = chars.next() {
+                    word.push(escaped);
+                }
+            }
+            _ => word.push(c),
+        }
+        in_word = true;
+    }
+    if in_word {
+        words.push((word, line.len()));
+    }
+
+    words
+}
+
+/// Finds the first preposition in `args`, preferring the longest one if several start at the same word.
+/// Returns the index of its first word, the number of words in it and its index in `PREPOSITIONS`.
+fn find_preposition(args: &[String]) -> Option<(usize, usize, usize)> {
+    (0..args.len()).find_map(|start| {
+        PREPOSITIONS
+            .iter()
+            .enumerate()
+            .flat_map(|(prep, spellings)| spellings.iter().map(move |s| (prep, s)))
+            .filter_map(|(prep, spelling)| {
+                let prep_words: Vec<&str> = spelling.split(' ').collect();
+                let candidate = args.get(start..start + prep_words.len())?;
+                candidate
+                    .iter()
+                    .zip(&prep_words)
+                    .all(|(arg, word)| arg.eq_ignore_ascii_case(word))
+                    .then_some((start, prep_words.len(), prep))
+            })
+            .max_by_key(|(_, len, _)| *len)
+    })
+}
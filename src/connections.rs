@@ -0,0 +1,183 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::Instant,
+};
+
+use parking_lot::{Mutex, RwLock};
+use tokio::{
+    runtime::Handle,
+    sync::{mpsc::UnboundedSender, oneshot, watch},
+};
+
+use crate::database::ID;
+
+/// A live network connection, as seen from the rest of the server
+#[derive(Debug, Clone)]
+pub struct Connection {
+    /// Unique for the lifetime of the server, unlike the player, which can be shared by the
+    /// connections of a fresh database before anyone can log in
+    pub id: ID,
+    /// Text sent here is written to the socket as is
+    output: UnboundedSender<String>,
+    /// Tasks waiting for a line, oldest first, which get the next lines instead of the command
+    /// parser
+    readers: Arc<Mutex<VecDeque<oneshot::Sender<String>>>>,
+    /// Describes where the connection comes from, as returned by connection_name()
+    pub name: String,
+    pub connected_at: Instant,
+    /// Shared between the clones, so the processing task can update it
+    last_input_at: Arc<Mutex<Instant>>,
+    /// Set to true when the server closes the connection
+    closing: Arc<watch::Sender<bool>>,
+}
+
+impl Connection {
+    pub fn new(id: ID, output: UnboundedSender<String>, name: String) -> Self {
+        let now = Instant::now();
+        Self {
+            id,
+            output,
+            readers: Arc::default(),
+            name,
+            connected_at: now,
+            last_input_at: Arc::new(Mutex::new(now)),
+            closing: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    /// Asks the tasks serving the connection to stop, which closes the socket once the output sent
+    /// so far has been written
+    pub fn close(&self) {
+        self.closing.send_replace(true);
+    }
+
+    /// Whether the server has closed the connection, as opposed to the client
+    pub fn is_closing(&self) -> bool {
+        *self.closing.borrow()
+    }
+
+    /// Changes when the connection is closed by the server
+    pub fn closing(&self) -> watch::Receiver<bool> {
+        self.closing.subscribe()
+    }
+
+    pub fn last_input_at(&self) -> Instant {
+        *self.last_input_at.lock()
+    }
+
+    /// Records that a line was just received
+    pub fn touch(&self) {
+        *self.last_input_at.lock() = Instant::now();
+    }
+
+    /// Queues up for the next line received, which is handed to the returned receiver instead of
+    /// the command parser. Returns `None` if the connection is already closed.
+    pub fn register_reader(&self) -> Option<oneshot::Receiver<String>> {
+        if self.is_closing() {
+            return None;
+        }
+        let (reader, line) = oneshot::channel();
+        self.readers.lock().push_back(reader);
+        Some(line)
+    }
+
+    /// Blocks the current thread until `line`, from register_reader(), is received, and returns it.
+    /// Returns `None` if the connection is closed first.
+    pub fn wait_for_line(&self, line: oneshot::Receiver<String>) -> Option<String> {
+        let mut closing = self.closing();
+        if *closing.borrow() {
+            return None;
+        }
+        Handle::current().block_on(async {
+            tokio::select! {
+                biased;
+                _ = closing.changed() => None,
+                line = line => line.ok(),
+            }
+        })
+    }
+
+    /// Hands `line` to the task that has been waiting for a line the longest.
+    /// Returns the line back if no task is waiting for one.
+    pub fn give_to_reader(&self, mut line: String) -> Option<String> {
+        let mut readers = self.readers.lock();
+        while let Some(reader) = readers.pop_front() {
+            // Readers stop waiting when the connection is closed
+            match reader.send(line) {
+                Ok(()) => return None,
+                Err(returned) => line = returned,
+            }
+        }
+        Some(line)
+    }
+
+    /// Sends `text` as one or more lines, each terminated by CRLF.
+    /// Returns `false` if the connection has been closed in the meantime.
+    pub fn send_line(&self, text: &str) -> bool {
+        self.output.send(crlf(text)).is_ok()
+    }
+}
+
+/// The live connections, keyed by the player they're logged in as (or their own negative ID, before
+/// they've logged in)
+#[derive(Debug, Default)]
+pub struct Connections {
+    by_player: HashMap<ID, Connection>,
+}
+
+impl Connections {
+    pub fn share(self) -> SharedConnections {
+        Arc::new(RwLock::new(self))
+    }
+
+    pub fn get(&self, player: ID) -> Option<&Connection> {
+        self.by_player.get(&player)
+    }
+
+    /// Players with a connection, oldest connection first. Connections that haven't logged in yet
+    /// are only included if `include_all` is true.
+    pub fn players(&self, include_all: bool) -> Vec<ID> {
+        let mut connections: Vec<(&ID, &Connection)> = self
+            .by_player
+            .iter()
+            .filter(|(&player, _)| include_all || player >= 0)
+            .collect();
+        connections.sort_by_key(|(_, c)| c.connected_at);
+        connections.into_iter().map(|(&player, _)| player).collect()
+    }
+
+    /// Registers `connection` for `player`, replacing any connection the player had before.
+    /// Returns the replaced connection, if any.
+    pub fn insert(&mut self, player: ID, connection: Connection) -> Option<Connection> {
+        self.by_player.insert(player, connection)
+    }
+
+    /// Moves the connection registered for `from` (a not yet logged in connection) to `to`.
+    /// Returns the connection `to` had before, if any.
+    pub fn rename(&mut self, from: ID, to: ID) -> Option<Connection> {
+        let connection = self.by_player.remove(&from)?;
+        self.by_player.insert(to, connection)
+    }
+
+    /// Unregisters `player`, but only if it's still associated with the connection `connection_id`.
+    /// Returns whether it was.
+    pub fn remove(&mut self, player: ID, connection_id: ID) -> bool {
+        let registered = self.get(player).map(|c| c.id) == Some(connection_id);
+        if registered {
+            self.by_player.remove(&player);
+        }
+        registered
+    }
+}
+
+pub type SharedConnections = Arc<RwLock<Connections>>;
+
+/// Normalizes the line endings in `text` to CRLF, adding one at the end
+pub fn crlf(text: &str) -> String {
+    let mut out: String = text.lines().flat_map(|line| [line, "\r\n"]).collect();
+    if out.is_empty() {
+        out.push_str("\r\n");
+    }
+    out
+}
@@ -0,0 +1,334 @@
+// Password hashing for the crypt() builtin
+// Implements the SHA-256 ("$5$") and SHA-512 ("$6$") variants of Unix crypt, as described in
+// https://www.akkadia.org/drepper/SHA-crypt.txt, as well as the traditional DES-based one, which
+// existing MOO databases are full of. Hashes are interchangeable with the ones made by MOO servers
+// built against glibc.
+
+use rand::Rng;
+use sha2::{Digest, Sha256, Sha512};
+
+/// Alphabet of the base64 variant used by crypt
+const ITOA64: &[u8] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+const SALT_MAX_LEN: usize = 16;
+const ROUNDS_DEFAULT: usize = 5000;
+const ROUNDS_MIN: usize = 1000;
+const ROUNDS_MAX: usize = 999_999_999;
+
+/// Order in which the bytes of the final SHA-256 digest are encoded, three at a time
+const SHA256_ORDER: &[usize] = &[
+    0, 10, 20, 21, 1, 11, 12, 22, 2, 3, 13, 23, 24, 4, 14, 15, 25, 5, 6, 16, 26, 27, 7, 17, 18, 28,
+    8, 9, 19, 29, 31, 30,
+];
+
+/// Order in which the bytes of the final SHA-512 digest are encoded, three at a time
+const SHA512_ORDER: &[usize] = &[
+    0, 21, 42, 22, 43, 1, 44, 2, 23, 3, 24, 45, 25, 46, 4, 47, 5, 26, 6, 27, 48, 28, 49, 7, 50, 8,
+    29, 9, 30, 51, 31, 52, 10, 53, 11, 32, 12, 33, 54, 34, 55, 13, 56, 14, 35, 15, 36, 57, 37, 58,
+    16, 59, 17, 38, 18, 39, 60, 40, 61, 19, 62, 20, 41, 63,
+];
+
+/// Hashes `password` with the algorithm, rounds and salt given in `setting`, which is either just a
+/// prefix (like "$6$"), a prefix and a salt, or a whole hash produced earlier.
+/// Comparing `crypt(password, hash)` with `hash` is therefore enough to check a password.
+/// If no salt is given, a random one is generated.
+/// A `setting` without a prefix is a two character salt for the traditional DES-based algorithm.
+///
+/// Returns `None` if the algorithm isn't supported, or the rounds or the DES salt are invalid.
+pub fn crypt(password: &str, setting: &str) -> Option<String> {
+    let (prefix, rest) = if let Some(rest) = setting.strip_prefix("$5$") {
+        ("$5$", rest)
+    } else if let Some(rest) = setting.strip_prefix("$6$") {
+        ("$6$", rest)
+    } else if !setting.starts_with('$') {
+        return des_crypt(password, setting);
+    } else {
+        return None;
+    };
+
+    // An explicit number of rounds is kept in the output, even if it's the default
+    let (rounds, rest) = match rest.strip_prefix("rounds=") {
+        Some(r) => {
+            let (n, rest) = r.split_once('$')?;
+            let n = n.parse::<usize>().ok()?;
+            (Some(n.clamp(ROUNDS_MIN, ROUNDS_MAX)), rest)
+        }
+        None => (None, rest),
+    };
+
+    let salt: String = rest
+        .split('$')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .take(SALT_MAX_LEN)
+        .collect();
+    let salt = if salt.is_empty() { random_salt() } else { salt };
+
+    let hash = match prefix {
+        "$5$" => encode(
+            &sha_crypt::<Sha256>(password.as_bytes(), salt.as_bytes(), rounds),
+            SHA256_ORDER,
+        ),
+        _ => encode(
+            &sha_crypt::<Sha512>(password.as_bytes(), salt.as_bytes(), rounds),
+            SHA512_ORDER,
+        ),
+    };
+    let rounds = rounds.map(|n| format!("rounds={}$", n)).unwrap_or_default();
+    Some(format!("{}{}{}${}", prefix, rounds, salt, hash))
+}
+
+fn random_salt() -> String {
+    let mut rng = rand::thread_rng();
+    (0..SALT_MAX_LEN)
+        .map(|_| ITOA64[rng.gen_range(0..ITOA64.len())] as char)
+        .collect()
+}
+
+/// Bytes `source` repeated up to a length of `len`
+fn repeated(source: &[u8], len: usize) -> Vec<u8> {
+    source.iter().cycle().take(len).copied().collect()
+}
+
+fn sha_crypt<D: Digest>(password: &[u8], salt: &[u8], rounds: Option<usize>) -> Vec<u8> {
+    let mut b = D::new();
+    b.update(password);
+    b.update(salt);
+    b.update(password);
+    let b = b.finalize();
+
+    let mut a = D::new();
+    a.update(password);
+    a.update(salt);
+    a.update(repeated(&b, password.len()));
+    let mut n = password.len();
+    while n > 0 {
+        if n & 1 == 1 {
+            a.update(&b);
+        } else {
+            a.update(password);
+        }
+        n >>= 1;
+    }
+    let a = a.finalize();
+
+    let mut dp = D::new();
+    for _ in 0..password.len() {
+        dp.update(password);
+    }
+    let p = repeated(&dp.finalize(), password.len());
+
+    let mut ds = D::new();
+    for _ in 0..16 + a[0] as usize {
+        ds.update(salt);
+    }
+    let s = repeated(&ds.finalize(), salt.len());
+
+    let mut c = a.to_vec();
+    for i in 0..rounds.unwrap_or(ROUNDS_DEFAULT) {
+        let mut d = D::new();
+        if i % 2 == 1 {
+            d.update(&p);
+        } else {
+            d.update(&c);
+        }
+        if i % 3 != 0 {
+            d.update(&s);
+        }
+        if i % 7 != 0 {
+            d.update(&p);
+        }
+        if i % 2 == 1 {
+            d.update(&c);
+        } else {
+            d.update(&p);
+        }
+        c = d.finalize().to_vec();
+    }
+    c
+}
+
+/// Encodes the digest bytes in `order`, three bytes into four characters, least significant bits first
+fn encode(digest: &[u8], order: &[usize]) -> String {
+    let mut out = String::new();
+    for group in order.chunks(3) {
+        // The last group is incomplete, its missing bytes count as zero
+        let (w, chars) = match *group {
+            [b2, b1, b0] => (
+                (digest[b2] as u32) << 16 | (digest[b1] as u32) << 8 | digest[b0] as u32,
+                4,
+            ),
+            [b1, b0] => ((digest[b1] as u32) << 8 | digest[b0] as u32, 3),
+            [b0] => (digest[b0] as u32, 2),
+            _ => unreachable!(),
+        };
+        for i in 0..chars {
+            out.push(ITOA64[(w >> (6 * i)) as usize & 0x3f] as char);
+        }
+    }
+    out
+}
+
+/// Initial permutation of DES, bits numbered from 1 for the most significant one
+const DES_IP: [u8; 64] = [
+    58, 50, 42, 34, 26, 18, 10, 2, 60, 52, 44, 36, 28, 20, 12, 4, 62, 54, 46, 38, 30, 22, 14, 6,
+    64, 56, 48, 40, 32, 24, 16, 8, 57, 49, 41, 33, 25, 17, 9, 1, 59, 51, 43, 35, 27, 19, 11, 3, 61,
+    53, 45, 37, 29, 21, 13, 5, 63, 55, 47, 39, 31, 23, 15, 7,
+];
+
+/// Final permutation of DES, the inverse of the initial one
+const DES_FP: [u8; 64] = [
+    40, 8, 48, 16, 56, 24, 64, 32, 39, 7, 47, 15, 55, 23, 63, 31, 38, 6, 46, 14, 54, 22, 62, 30,
+    37, 5, 45, 13, 53, 21, 61, 29, 36, 4, 44, 12, 52, 20, 60, 28, 35, 3, 43, 11, 51, 19, 59, 27,
+    34, 2, 42, 10, 50, 18, 58, 26, 33, 1, 41, 9, 49, 17, 57, 25,
+];
+
+/// Expansion of the right half of the block to 48 bits, which the salt alters
+const DES_E: [u8; 48] = [
+    32, 1, 2, 3, 4, 5, 4, 5, 6, 7, 8, 9, 8, 9, 10, 11, 12, 13, 12, 13, 14, 15, 16, 17, 16, 17, 18,
+    19, 20, 21, 20, 21, 22, 23, 24, 25, 24, 25, 26, 27, 28, 29, 28, 29, 30, 31, 32, 1,
+];
+
+/// Permutation of the output of the S-boxes
+const DES_P: [u8; 32] = [
+    16, 7, 20, 21, 29, 12, 28, 17, 1, 15, 23, 26, 5, 18, 31, 10, 2, 8, 24, 14, 32, 27, 3, 9, 19,
+    13, 30, 6, 22, 11, 4, 25,
+];
+
+/// Selection of the 56 key bits, leaving out the parity bits
+const DES_PC1: [u8; 56] = [
+    57, 49, 41, 33, 25, 17, 9, 1, 58, 50, 42, 34, 26, 18, 10, 2, 59, 51, 43, 35, 27, 19, 11, 3, 60,
+    52, 44, 36, 63, 55, 47, 39, 31, 23, 15, 7, 62, 54, 46, 38, 30, 22, 14, 6, 61, 53, 45, 37, 29,
+    21, 13, 5, 28, 20, 12, 4,
+];
+
+/// Selection of the 48 bits of each round's key
+const DES_PC2: [u8; 48] = [
+    14, 17, 11, 24, 1, 5, 3, 28, 15, 6, 21, 10, 23, 19, 12, 4, 26, 8, 16, 7, 27, 20, 13, 2, 41, 52,
+    31, 37, 47, 55, 30, 40, 51, 45, 33, 48, 44, 49, 39, 56, 34, 53, 46, 42, 50, 36, 29, 32,
+];
+
+/// How far the halves of the key are rotated before each round
+const DES_SHIFTS: [u32; 16] = [1, 1, 2, 2, 2, 2, 2, 2, 1, 2, 2, 2, 2, 2, 2, 1];
+
+/// The S-boxes, each one row after the other
+const DES_S: [[u8; 64]; 8] = [
+    [
+        14, 4, 13, 1, 2, 15, 11, 8, 3, 10, 6, 12, 5, 9, 0, 7, 0, 15, 7, 4, 14, 2, 13, 1, 10, 6, 12,
+        11, 9, 5, 3, 8, 4, 1, 14, 8, 13, 6, 2, 11, 15, 12, 9, 7, 3, 10, 5, 0, 15, 12, 8, 2, 4, 9,
+        1, 7, 5, 11, 3, 14, 10, 0, 6, 13,
+    ],
+    [
+        15, 1, 8, 14, 6, 11, 3, 4, 9, 7, 2, 13, 12, 0, 5, 10, 3, 13, 4, 7, 15, 2, 8, 14, 12, 0, 1,
+        10, 6, 9, 11, 5, 0, 14, 7, 11, 10, 4, 13, 1, 5, 8, 12, 6, 9, 3, 2, 15, 13, 8, 10, 1, 3, 15,
+        4, 2, 11, 6, 7, 12, 0, 5, 14, 9,
+    ],
+    [
+        10, 0, 9, 14, 6, 3, 15, 5, 1, 13, 12, 7, 11, 4, 2, 8, 13, 7, 0, 9, 3, 4, 6, 10, 2, 8, 5,
+        14, 12, 11, 15, 1, 13, 6, 4, 9, 8, 15, 3, 0, 11, 1, 2, 12, 5, 10, 14, 7, 1, 10, 13, 0, 6,
+        9, 8, 7, 4, 15, 14, 3, 11, 5, 2, 12,
+    ],
+    [
+        7, 13, 14, 3, 0, 6, 9, 10, 1, 2, 8, 5, 11, 12, 4, 15, 13, 8, 11, 5, 6, 15, 0, 3, 4, 7, 2,
+        12, 1, 10, 14, 9, 10, 6, 9, 0, 12, 11, 7, 13, 15, 1, 3, 14, 5, 2, 8, 4, 3, 15, 0, 6, 10, 1,
+        13, 8, 9, 4, 5, 11, 12, 7, 2, 14,
+    ],
+    [
+        2, 12, 4, 1, 7, 10, 11, 6, 8, 5, 3, 15, 13, 0, 14, 9, 14, 11, 2, 12, 4, 7, 13, 1, 5, 0, 15,
+        10, 3, 9, 8, 6, 4, 2, 1, 11, 10, 13, 7, 8, 15, 9, 12, 5, 6, 3, 0, 14, 11, 8, 12, 7, 1, 14,
+        2, 13, 6, 15, 0, 9, 10, 4, 5, 3,
+    ],
+    [
+        12, 1, 10, 15, 9, 2, 6, 8, 0, 13, 3, 4, 14, 7, 5, 11, 10, 15, 4, 2, 7, 12, 9, 5, 6, 1, 13,
+        14, 0, 11, 3, 8, 9, 14, 15, 5, 2, 8, 12, 3, 7, 0, 4, 10, 1, 13, 11, 6, 4, 3, 2, 12, 9, 5,
+        15, 10, 11, 14, 1, 7, 6, 0, 8, 13,
+    ],
+    [
+        4, 11, 2, 14, 15, 0, 8, 13, 3, 12, 9, 7, 5, 10, 6, 1, 13, 0, 11, 7, 4, 9, 1, 10, 14, 3, 5,
+        12, 2, 15, 8, 6, 1, 4, 11, 13, 12, 3, 7, 14, 10, 15, 6, 8, 0, 5, 9, 2, 6, 11, 13, 8, 1, 4,
+        10, 7, 9, 5, 0, 15, 14, 2, 3, 12,
+    ],
+    [
+        13, 2, 8, 4, 6, 15, 11, 1, 10, 9, 3, 14, 5, 0, 12, 7, 1, 15, 13, 8, 10, 3, 7, 4, 12, 5, 6,
+        11, 0, 14, 9, 2, 7, 11, 4, 1, 9, 12, 14, 2, 0, 6, 10, 13, 15, 3, 5, 8, 2, 1, 14, 7, 4, 10,
+        8, 13, 15, 12, 9, 0, 3, 5, 6, 11,
+    ],
+];
+
+/// Traditional crypt: a block of zeros encrypted 25 times with DES, using the first 8 characters of
+/// the password as the key, and a salt of two characters that changes the expansion of each round.
+/// Characters of `setting` after the salt are ignored, so a whole hash can be passed as well.
+fn des_crypt(password: &str, setting: &str) -> Option<String> {
+    let salt: String = setting.chars().take(2).collect();
+    let salt_bits = salt
+        .bytes()
+        .map(|c| ITOA64.iter().position(|&a| a == c))
+        .collect::<Option<Vec<usize>>>()?;
+    if salt_bits.len() < 2 {
+        return None;
+    }
+    // Each bit of the salt swaps two bits of the expansion
+    let mut expansion = DES_E;
+    for (i, bits) in salt_bits.iter().enumerate() {
+        for j in 0..6 {
+            if bits >> j & 1 == 1 {
+                expansion.swap(6 * i + j, 6 * i + j + 24);
+            }
+        }
+    }
+
+    // The 7 bits of each character fill a byte of the key, leaving out the parity bit
+    let key = password.bytes().take(8).enumerate().fold(0, |key, (i, c)| {
+        key | ((c as u64) << 1 & 0xfe) << (56 - 8 * i)
+    });
+    let subkeys = des_subkeys(key);
+    let mut block = 0;
+    for _ in 0..25 {
+        block = des_encrypt(block, &subkeys, &expansion);
+    }
+
+    // 64 bits padded to 66, six at a time, most significant first
+    let bits = (block as u128) << 2;
+    let hash: String = (0..11)
+        .map(|i| ITOA64[(bits >> (60 - 6 * i)) as usize & 0x3f] as char)
+        .collect();
+    Some(format!("{}{}", salt, hash))
+}
+
+/// The keys of the 16 rounds
+fn des_subkeys(key: u64) -> [u64; 16] {
+    let halves = permute(key, 64, &DES_PC1);
+    let (mut c, mut d) = (halves >> 28, halves & 0xfff_ffff);
+    let mut subkeys = [0; 16];
+    for (subkey, shift) in subkeys.iter_mut().zip(DES_SHIFTS) {
+        c = (c << shift | c >> (28 - shift)) & 0xfff_ffff;
+        d = (d << shift | d >> (28 - shift)) & 0xfff_ffff;
+        *subkey = permute(c << 28 | d, 56, &DES_PC2);
+    }
+    subkeys
+}
+
+fn des_encrypt(block: u64, subkeys: &[u64; 16], expansion: &[u8; 48]) -> u64 {
+    let block = permute(block, 64, &DES_IP);
+    let (mut l, mut r) = (block >> 32, block & 0xffff_ffff);
+    for subkey in subkeys {
+        let x = permute(r, 32, expansion) ^ subkey;
+        // Each S-box turns 6 bits into 4: the outer bits select the row, the inner ones the column
+        let s = DES_S.iter().enumerate().fold(0, |s, (i, sbox)| {
+            let bits = (x >> (42 - 6 * i)) as usize & 0x3f;
+            let row = (bits >> 4 & 2) | (bits & 1);
+            let column = bits >> 1 & 0xf;
+            s << 4 | sbox[row * 16 + column] as u64
+        });
+        (l, r) = (r, l ^ permute(s, 32, &DES_P));
+    }
+    permute(r << 32 | l, 64, &DES_FP)
+}
+
+/// Picks the bits of `input`, which is `len` bits long, in the order given by `table`
+fn permute(input: u64, len: u32, table: &[u8]) -> u64 {
+    table
+        .iter()
+        .fold(0, |out, &bit| out << 1 | (input >> (len - bit as u32) & 1))
+}
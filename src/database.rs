@@ -11,6 +11,9 @@ use crate::error::{Error::*, RhaiResult};
 
 pub type ID = rhai::INT;
 
+/// The wizard player of a fresh database, which bootstrapping connections are logged in as
+pub const WIZARD: ID = 1;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Database {
     highest_object_number: ID,
@@ -26,26 +29,35 @@ impl Database {
         // TODO move object creation out a simple script that creates something like minimal.db
         let mut root = Object::new(0, -1, -1);
         root.name = "Root Object".to_string();
-        root.f = true; // TODO check this is the same in the original minimal core
-        root.properties.insert(
-            "nothing".to_string(),
-            Property::new(
-                PropertyInfo::new(0, PropertyPerms::new(true, false, false), None),
-                Dynamic::from(crate::api::ObjectProxy::new(-1)),
-            ),
-        );
+        // TODO check this is the same in the original minimal core
+        root.f = true;
+
+        // Sentinel values, like $nothing, $ambiguous_match and $failed_match in Moo
+        for (name, id) in [
+            ("nothing", -1),
+            ("ambiguous_match", -2),
+            ("failed_match", -3),
+        ] {
+            root.properties.insert(
+                name.to_string(),
+                Property::new(
+                    PropertyInfo::new(0, PropertyPerms::new(true, false, false), None),
+                    Dynamic::from(crate::api::ObjectProxy::new(id)),
+                ),
+            );
+        }
         objects.insert(0, root);
 
-        let mut wizard = Object::new(1, -1, -1);
+        let mut wizard = Object::new(WIZARD, -1, -1);
         wizard.name = "Wizard".to_string();
         wizard.wizard = true;
         wizard.programmer = true;
         wizard.is_player = true;
         wizard.f = false;
-        objects.insert(1, wizard);
+        objects.insert(WIZARD, wizard);
 
         Self {
-            highest_object_number: 1,
+            highest_object_number: WIZARD,
             objects,
         }
     }
@@ -73,7 +85,7 @@ impl Database {
             .unwrap_or(false)
     }
 
-    fn is_wizard(&self, programmer_id: ID) -> bool {
+    pub fn is_wizard(&self, programmer_id: ID) -> bool {
         self.objects
             .get(&programmer_id)
             .map(|o| o.wizard)
@@ -84,6 +96,23 @@ impl Database {
         self.is_owner(object_id, programmer_id) || self.is_wizard(programmer_id)
     }
 
+    fn is_programmer(&self, programmer_id: ID) -> bool {
+        self.objects
+            .get(&programmer_id)
+            .map(|o| o.programmer)
+            .unwrap_or(false)
+    }
+
+    fn can_read_object(&self, object_id: ID, programmer_id: ID) -> bool {
+        self.owner_or_wizard(object_id, programmer_id)
+            || self.objects.get(&object_id).map(|o| o.r).unwrap_or(false)
+    }
+
+    fn can_write_object(&self, object_id: ID, programmer_id: ID) -> bool {
+        self.owner_or_wizard(object_id, programmer_id)
+            || self.objects.get(&object_id).map(|o| o.w).unwrap_or(false)
+    }
+
     pub fn create(&mut self, parent: ID, owner: Option<ID>, programmer: ID) -> RhaiResult<ID> {
         // Either the given parent object must be #-1 or valid and fertile (i.e., its f bit must be set) or else the programmer must own parent or be a wizard; otherwise E_PERM is raised.
         if !(parent == -1
@@ -112,7 +141,12 @@ impl Database {
             Some(-1) => id,
             Some(o) => o,
         };
-        self.objects.insert(id, Object::new(id, parent, real_owner));
+        let mut object = Object::new(id, parent, real_owner);
+        if let Some(parent) = self.objects.get_mut(&parent) {
+            parent.children.push(id);
+            object.properties = inherited_slots(&parent.properties, real_owner);
+        }
+        self.objects.insert(id, object);
 
         Ok(id)
     }
@@ -141,6 +175,19 @@ impl Database {
         ancestors
     }
 
+    /// The object defining each property that the children of `id` inherit, which is `id` itself or
+    /// one of its ancestors
+    fn property_definers(&self, id: ID) -> HashMap<String, ID> {
+        self.ancestors_and_self(id)
+            .into_iter()
+            .flat_map(|a| {
+                self.objects[&a]
+                    .defined_properties()
+                    .map(move |n| (n.clone(), a))
+            })
+            .collect()
+    }
+
     fn descendants_and_self(&self, id: ID) -> Vec<ID> {
         let mut descendants = vec![id];
         for child in self.objects[&id].children.iter() {
@@ -178,25 +225,95 @@ impl Database {
             let ancestor_properties: HashSet<String> = self
                 .ancestors_and_self(parent)
                 .iter()
-                .map(|id| self.objects[id].properties.keys().cloned())
-                .flatten()
+                .flat_map(|id| self.objects[id].defined_properties())
+                .cloned()
                 .collect();
             for descendant in self.descendants_and_self(id) {
-                for property in self.objects[&descendant].properties.keys() {
+                for property in self.objects[&descendant].defined_properties() {
                     if ancestor_properties.contains(property) {
                         bail!(E_INVARG);
                     }
                 }
             }
+        }
+
+        let old_parent = self.objects[&id].parent;
+        // Properties inherited from the ancestors the old and new parents share are kept, the others
+        // are gone, along with their values
+        let new_definers = self.property_definers(parent);
+        let lost: Vec<String> = self
+            .property_definers(old_parent)
+            .into_iter()
+            .filter(|(name, definer)| new_definers.get(name) != Some(definer))
+            .map(|(name, _)| name)
+            .collect();
+        if let Some(old_parent) = self.objects.get_mut(&old_parent) {
+            old_parent.children.retain(|child| *child != id);
+        }
+        if let Some(parent) = self.objects.get_mut(&parent) {
+            parent.children.push(id);
+        }
+        self.objects.get_mut(&id).unwrap().parent = parent;
 
-            // TODO handle adding / removing inherited properties
+        // The properties of the new ancestors start out clear.
+        // Parents come before their children, so each object can copy its parent's new slots
+        for descendant in self.descendants_and_self(id) {
+            let object = self.objects.get_mut(&descendant).unwrap();
+            for name in &lost {
+                object.properties.remove(name);
+            }
+            let object = &self.objects[&descendant];
+            let slots = match self.objects.get(&object.parent) {
+                None => continue,
+                Some(parent) => inherited_slots(&parent.properties, object.owner),
+            };
+            // Slots that are still there are kept, with their values
+            let object = self.objects.get_mut(&descendant).unwrap();
+            for (name, slot) in slots {
+                object.properties.entry(name).or_insert(slot);
+            }
         }
+        Ok(())
+    }
 
-        let mut object = self.objects.get_mut(&id).unwrap();
-        object.parent = parent;
+    /// Checks that `programmer` may move `what` to `to` at all, before asking `to` if it accepts it
+    pub fn check_move(&self, what: ID, to: ID, programmer: ID) -> RhaiResult<()> {
+        if !self.valid(what) || (!self.valid(to) && to != -1) {
+            bail!(E_INVARG);
+        }
+        if !self.owner_or_wizard(what, programmer) {
+            bail!(E_PERM);
+        }
         Ok(())
     }
 
+    /// Moves `what` into the contents of `to`, or nowhere if `to` is #-1.
+    /// Returns where it was before.
+    pub fn move_object(&mut self, what: ID, to: ID) -> RhaiResult<ID> {
+        // Verbs called since check_move() may have changed things
+        if !self.valid(what) || (!self.valid(to) && to != -1) {
+            bail!(E_INVARG);
+        }
+        // Nothing can end up inside itself
+        let mut current = to;
+        while current != -1 {
+            if current == what {
+                bail!(E_RECMOVE);
+            }
+            current = self.objects[&current].location;
+        }
+
+        let from = self.objects[&what].location;
+        if let Some(from) = self.objects.get_mut(&from) {
+            from.contents.retain(|id| *id != what);
+        }
+        if let Some(to) = self.objects.get_mut(&to) {
+            to.contents.push(what);
+        }
+        self.objects.get_mut(&what).unwrap().location = to;
+        Ok(from)
+    }
+
     pub fn valid(&self, id: ID) -> bool {
         self.objects.contains_key(&id)
     }
@@ -208,6 +325,63 @@ impl Database {
             .unwrap_or(-1)
     }
 
+    pub fn is_player(&self, id: ID) -> bool {
+        self.objects
+            .get(&id)
+            .map(|object| object.is_player)
+            .unwrap_or(false)
+    }
+
+    pub fn location(&self, id: ID) -> ID {
+        self.objects
+            .get(&id)
+            .map(|object| object.location)
+            .unwrap_or(-1)
+    }
+
+    pub fn children(&self, id: ID) -> &[ID] {
+        self.objects
+            .get(&id)
+            .map(|object| object.children.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The parent of the object, its parent, and so on
+    pub fn ancestors(&self, id: ID) -> Vec<ID> {
+        match self.valid(id) {
+            true => self.ancestors_and_self(id).split_off(1),
+            false => Vec::new(),
+        }
+    }
+
+    /// The children of the object, their children, and so on, each object before its children
+    pub fn descendants(&self, id: ID) -> Vec<ID> {
+        match self.valid(id) {
+            true => self.descendants_and_self(id).split_off(1),
+            false => Vec::new(),
+        }
+    }
+
+    pub fn contents(&self, id: ID) -> &[ID] {
+        self.objects
+            .get(&id)
+            .map(|object| object.contents.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The strings in the `aliases` property of the object, if it has one
+    pub fn aliases(&self, id: ID) -> Vec<String> {
+        match self.property_value(id, "aliases") {
+            Ok(aliases) if aliases.is::<rhai::Array>() => aliases
+                .clone()
+                .cast::<rhai::Array>()
+                .into_iter()
+                .filter_map(|alias| alias.into_string().ok())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
     pub fn get_highest_object_number(&self) -> ID {
         self.highest_object_number
     }
@@ -242,14 +416,51 @@ impl Database {
         Ok(())
     }
 
-    pub fn get_property_dynamic(&self, id: ID, property: &str) -> RhaiResult<Dynamic> {
+    pub fn set_player_flag(&mut self, id: ID, value: bool, programmer: ID) -> RhaiResult<()> {
+        // If object is invalid, E_INVARG is raised. If the programmer is not a wizard, then E_PERM is raised.
+        if !self.valid(id) {
+            bail!(E_INVARG);
+        }
+        if !self.is_wizard(programmer) {
+            bail!(E_PERM);
+        }
+        self.objects.get_mut(&id).unwrap().is_player = value;
+        Ok(())
+    }
+
+    pub fn get_property_dynamic(
+        &self,
+        id: ID,
+        property: &str,
+        programmer: ID,
+    ) -> RhaiResult<Dynamic> {
+        let value = self.property_value(id, property)?;
+        // The object's own slot decides, even if the value is inherited
+        let slot = &self.objects[&id].properties[property];
+        if !self.property_allows(slot, programmer, |perms| perms.r) {
+            bail!(E_PERM);
+        }
+        // Password hashes of players are only for the login code to see
+        if property == PASSWORD_PROPERTY && self.is_player(id) && !self.is_wizard(programmer) {
+            bail!(E_PERM);
+        }
+        Ok(value.clone())
+    }
+
+    /// The value of a property without any permission checks, for lookups done by the server itself.
+    /// Clear properties have the value of the nearest ancestor that has one.
+    pub fn property_value(&self, id: ID, property: &str) -> RhaiResult<&Dynamic> {
         if !self.valid(id) {
             bail!(E_INVIND);
         }
-        let o = &self.objects[&id];
-        match o.properties.get(property) {
-            None => bail!(E_PROPNF),
-            Some(p) => Ok(p.value.clone()),
+        let mut current = id;
+        loop {
+            let o = &self.objects[&current];
+            match o.properties.get(property) {
+                None => bail!(E_PROPNF),
+                Some(p) if p.clear && self.valid(o.parent) => current = o.parent,
+                Some(p) => return Ok(&p.value),
+            }
         }
     }
 
@@ -258,18 +469,26 @@ impl Database {
         id: ID,
         property: &str,
         value: Dynamic,
+        programmer: ID,
     ) -> RhaiResult<()> {
         if !self.valid(id) {
             bail!(E_INVIND);
         }
-        let o = self.objects.get_mut(&id).unwrap();
-        match o.properties.get_mut(property) {
+        match self.objects[&id].properties.get(property) {
             None => bail!(E_PROPNF),
-            Some(p) => {
-                p.value = value;
-                Ok(())
-            }
+            Some(p) if !self.property_allows(p, programmer, |perms| perms.w) => bail!(E_PERM),
+            Some(_) => (),
         }
+        let p = self
+            .objects
+            .get_mut(&id)
+            .unwrap()
+            .properties
+            .get_mut(property)
+            .unwrap();
+        p.value = value;
+        p.clear = false;
+        Ok(())
     }
 
     pub fn add_property(
@@ -278,18 +497,181 @@ impl Database {
         name: &str,
         value: Dynamic,
         info: PropertyInfo,
+        programmer: ID,
     ) -> RhaiResult<()> {
         if !self.valid(info.owner) || !self.valid(id) {
             bail!(E_INVARG);
         }
-        let o = self.objects.get_mut(&id).unwrap();
-
-        // TODO needs to check parent hierarchy
-        if o.properties.contains_key(name) {
+        // Only wizards may add properties owned by someone else
+        if !self.can_write_object(id, programmer)
+            || (info.owner != programmer && !self.is_wizard(programmer))
+        {
+            bail!(E_PERM);
+        }
+        // The object already has a slot for the properties its ancestors define, and the ones
+        // defined by its descendants would be hidden
+        let descendants = self.descendants_and_self(id);
+        if BUILTIN_PROPERTIES.contains(&name)
+            || descendants
+                .iter()
+                .any(|d| self.objects[d].properties.contains_key(name))
+        {
             bail!(E_INVARG)
         }
-        o.properties
+
+        self.objects
+            .get_mut(&id)
+            .unwrap()
+            .properties
             .insert(name.to_string(), Property::new(info, value));
+        // Descendants get a clear slot each, parents before their children
+        for descendant in descendants.into_iter().skip(1) {
+            let object = &self.objects[&descendant];
+            let slot = self.objects[&object.parent].properties[name].inherited_by(object.owner);
+            self.objects
+                .get_mut(&descendant)
+                .unwrap()
+                .properties
+                .insert(name.to_string(), slot);
+        }
+        Ok(())
+    }
+
+    /// Changes the owner and permissions of the property `name` on `id`, and renames it if
+    /// `info.new_name` is given. Only the object defining the property can rename it, which renames
+    /// the slots on the descendants as well.
+    pub fn set_property_info(
+        &mut self,
+        id: ID,
+        name: &str,
+        mut info: PropertyInfo,
+        programmer: ID,
+    ) -> RhaiResult<()> {
+        if !self.valid(id) || !self.valid(info.owner) {
+            bail!(E_INVARG);
+        }
+        let property = match self.objects[&id].properties.get(name) {
+            None => bail!(E_PROPNF),
+            Some(p) => p,
+        };
+        // Only wizards may give properties away
+        let wizard = self.is_wizard(programmer);
+        if (property.info.owner != programmer || info.owner != programmer) && !wizard {
+            bail!(E_PERM);
+        }
+
+        let new_name = info.new_name.take().filter(|new_name| new_name != name);
+        let descendants = self.descendants_and_self(id);
+        if let Some(new_name) = &new_name {
+            // Every object defining or inheriting a property with the new name has a slot for it
+            let taken = descendants
+                .iter()
+                .any(|d| self.objects[d].properties.contains_key(new_name));
+            if property.inherited || taken || BUILTIN_PROPERTIES.contains(&new_name.as_str()) {
+                bail!(E_INVARG);
+            }
+        }
+
+        let object = self.objects.get_mut(&id).unwrap();
+        object.properties.get_mut(name).unwrap().info = info;
+        if let Some(new_name) = new_name {
+            for descendant in descendants {
+                let properties = &mut self.objects.get_mut(&descendant).unwrap().properties;
+                if let Some(p) = properties.remove(name) {
+                    properties.insert(new_name.clone(), p);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the property `name` defined on `id`, along with its slots on the descendants
+    pub fn delete_property(&mut self, id: ID, name: &str, programmer: ID) -> RhaiResult<()> {
+        if !self.valid(id) {
+            bail!(E_INVARG);
+        }
+        match self.objects[&id].properties.get(name) {
+            Some(p) if !p.inherited => (),
+            _ => bail!(E_PROPNF),
+        }
+        if !self.can_write_object(id, programmer) {
+            bail!(E_PERM);
+        }
+        for descendant in self.descendants_and_self(id) {
+            self.objects
+                .get_mut(&descendant)
+                .unwrap()
+                .properties
+                .remove(name);
+        }
+        Ok(())
+    }
+
+    /// Names of the properties defined on `id` itself, sorted
+    pub fn properties(&self, id: ID, programmer: ID) -> RhaiResult<Vec<String>> {
+        if !self.valid(id) {
+            bail!(E_INVARG);
+        }
+        if !self.can_read_object(id, programmer) {
+            bail!(E_PERM);
+        }
+        let mut names: Vec<String> = self.objects[&id].defined_properties().cloned().collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// Whether `programmer` may do what `flag` allows with `property`, which it always may if it's
+    /// the property's owner or a wizard
+    fn property_allows(
+        &self,
+        property: &Property,
+        programmer: ID,
+        flag: impl Fn(&PropertyPerms) -> bool,
+    ) -> bool {
+        property.info.owner == programmer
+            || flag(&property.info.perms)
+            || self.is_wizard(programmer)
+    }
+
+    /// Whether the property inherits its value from the parent. Built-in properties never do.
+    pub fn is_clear_property(&self, id: ID, name: &str, programmer: ID) -> RhaiResult<bool> {
+        if !self.valid(id) {
+            bail!(E_INVARG);
+        }
+        if BUILTIN_PROPERTIES.contains(&name) {
+            return Ok(false);
+        }
+        match self.objects[&id].properties.get(name) {
+            None => bail!(E_PROPNF),
+            Some(p) if !self.property_allows(p, programmer, |perms| perms.r) => bail!(E_PERM),
+            Some(p) => Ok(p.clear),
+        }
+    }
+
+    /// Makes the property inherit its value from the parent again
+    pub fn clear_property(&mut self, id: ID, name: &str, programmer: ID) -> RhaiResult<()> {
+        if !self.valid(id) {
+            bail!(E_INVARG);
+        }
+        if BUILTIN_PROPERTIES.contains(&name) {
+            bail!(E_PERM);
+        }
+        match self.objects[&id].properties.get(name) {
+            None => bail!(E_PROPNF),
+            Some(p) if !self.property_allows(p, programmer, |perms| perms.w) => bail!(E_PERM),
+            // There's nothing to inherit from on the object defining the property
+            Some(p) if !p.inherited => bail!(E_INVARG),
+            Some(_) => (),
+        }
+        let p = self
+            .objects
+            .get_mut(&id)
+            .unwrap()
+            .properties
+            .get_mut(name)
+            .unwrap();
+        p.clear = true;
+        p.value = Dynamic::UNIT;
         Ok(())
     }
 
@@ -302,6 +684,148 @@ impl Database {
             Some(p) => Ok(&p.info),
         }
     }
+
+    pub fn add_verb(
+        &mut self,
+        id: ID,
+        info: VerbInfo,
+        args: VerbArgs,
+        programmer: ID,
+    ) -> RhaiResult<()> {
+        // If object is not valid, or info's owner is not valid, or names is empty, then E_INVARG is raised.
+        if !self.valid(id) || !self.valid(info.owner) || info.names.trim().is_empty() {
+            bail!(E_INVARG);
+        }
+
+        // The programmer must be a programmer, and must either own object or object must be writable.
+        // Only wizards may create verbs owned by someone else.
+        if !self.is_programmer(programmer)
+            || !self.can_write_object(id, programmer)
+            || (info.owner != programmer && !self.is_wizard(programmer))
+        {
+            bail!(E_PERM);
+        }
+
+        self.objects
+            .get_mut(&id)
+            .unwrap()
+            .verbs
+            .push(Verb::new(info, args));
+        Ok(())
+    }
+
+    pub fn delete_verb(&mut self, id: ID, desc: &VerbDesc, programmer: ID) -> RhaiResult<()> {
+        if !self.valid(id) {
+            bail!(E_INVARG);
+        }
+        let index = self.find_verb_index(id, desc)?;
+        if !self.is_programmer(programmer) || !self.can_write_object(id, programmer) {
+            bail!(E_PERM);
+        }
+        self.objects.get_mut(&id).unwrap().verbs.remove(index);
+        Ok(())
+    }
+
+    pub fn verb_code(&self, id: ID, desc: &VerbDesc, programmer: ID) -> RhaiResult<&str> {
+        if !self.valid(id) {
+            bail!(E_INVARG);
+        }
+        let verb = &self.objects[&id].verbs[self.find_verb_index(id, desc)?];
+        if !(verb.info.perms.r || verb.info.owner == programmer || self.is_wizard(programmer)) {
+            bail!(E_PERM);
+        }
+        Ok(&verb.code)
+    }
+
+    pub fn set_verb_code(
+        &mut self,
+        id: ID,
+        desc: &VerbDesc,
+        code: String,
+        programmer: ID,
+    ) -> RhaiResult<()> {
+        if !self.valid(id) {
+            bail!(E_INVARG);
+        }
+        let index = self.find_verb_index(id, desc)?;
+        let is_wizard = self.is_wizard(programmer);
+        let is_programmer = self.is_programmer(programmer);
+        let verb = &mut self.objects.get_mut(&id).unwrap().verbs[index];
+        if !is_programmer || !(verb.info.perms.w || verb.info.owner == programmer || is_wizard) {
+            bail!(E_PERM);
+        }
+        verb.code = code;
+        Ok(())
+    }
+
+    /// Finds the verb that `object.name()` would call, returning it along with the object defining it.
+    pub fn find_callable_verb(&self, id: ID, name: &str) -> RhaiResult<(ID, &Verb)> {
+        if !self.valid(id) {
+            bail!(E_INVIND);
+        }
+        self.ancestors_and_self(id)
+            .into_iter()
+            .find_map(|ancestor| {
+                self.objects[&ancestor]
+                    .verbs
+                    .iter()
+                    .find(|v| v.info.perms.x && v.info.matches(name))
+                    .map(|v| (ancestor, v))
+            })
+            .ok_or_else(|| E_VERBNF.into())
+    }
+
+    /// Finds a verb on `id` or its ancestors that can handle a command with the given verb and objects.
+    /// Unlike with `find_callable_verb`, the verb doesn't need to be executable.
+    pub fn find_command_verb(
+        &self,
+        id: ID,
+        verb: &str,
+        dobj: ID,
+        prep: PrepSpec,
+        iobj: ID,
+    ) -> Option<(ID, &Verb)> {
+        if !self.valid(id) {
+            return None;
+        }
+        self.ancestors_and_self(id)
+            .into_iter()
+            .find_map(|ancestor| {
+                self.objects[&ancestor]
+                    .verbs
+                    .iter()
+                    .find(|v| v.info.matches(verb) && v.args.matches(id, dobj, prep, iobj))
+                    .map(|v| (ancestor, v))
+            })
+    }
+
+    /// Finds a verb by name on `id` or its ancestors, regardless of its permissions and argument specifiers.
+    pub fn find_verb_named(&self, id: ID, name: &str) -> Option<(ID, &Verb)> {
+        if !self.valid(id) {
+            return None;
+        }
+        self.ancestors_and_self(id)
+            .into_iter()
+            .find_map(|ancestor| {
+                self.objects[&ancestor]
+                    .verbs
+                    .iter()
+                    .find(|v| v.info.matches(name))
+                    .map(|v| (ancestor, v))
+            })
+    }
+
+    fn find_verb_index(&self, id: ID, desc: &VerbDesc) -> RhaiResult<usize> {
+        let verbs = &self.objects[&id].verbs;
+        match desc {
+            VerbDesc::Index(i) if *i < verbs.len() => Ok(*i),
+            VerbDesc::Index(_) => bail!(E_VERBNF),
+            VerbDesc::Name(name) => match verbs.iter().position(|v| v.info.matches(name)) {
+                None => bail!(E_VERBNF),
+                Some(i) => Ok(i),
+            },
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -335,8 +859,14 @@ pub struct Object {
     /// is the object fertile?
     f: bool,
 
-    /// storage for non-built-in properties
+    /// storage for non-built-in properties, both the ones defined on the object and its slots for
+    /// the inherited ones
     properties: HashMap<String, Property>,
+
+    // Verbs on Objects
+    // https://www.sindome.org/moo-manual.html#verbs-on-objects
+    #[serde(default)]
+    verbs: Vec<Verb>,
 }
 
 impl Object {
@@ -356,8 +886,17 @@ impl Object {
             w: false,
             f: false,
             properties: HashMap::new(),
+            verbs: Vec::new(),
         }
     }
+
+    /// Names of the properties defined on the object itself, as opposed to inherited
+    fn defined_properties(&self) -> impl Iterator<Item = &String> {
+        self.properties
+            .iter()
+            .filter(|(_, p)| !p.inherited)
+            .map(|(name, _)| name)
+    }
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -390,14 +929,213 @@ impl PropertyInfo {
     }
 }
 
+/// A property defined on an object, or an object's slot for a property defined on an ancestor
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Property {
     info: PropertyInfo,
     value: Dynamic,
+    /// the value is inherited from the parent, and `value` is ignored
+    #[serde(default)]
+    clear: bool,
+    /// the property is defined on an ancestor
+    #[serde(default)]
+    inherited: bool,
 }
 
 impl Property {
     fn new(info: PropertyInfo, value: Dynamic) -> Self {
-        Self { info, value }
+        Self {
+            info,
+            value,
+            clear: false,
+            inherited: false,
+        }
+    }
+
+    /// A clear slot for this property on a child owned by `owner`.
+    /// Like in MOO, the child's owner owns the slot if the property has the `c` permission.
+    fn inherited_by(&self, owner: ID) -> Self {
+        let mut info = self.info.clone();
+        if info.perms.c {
+            info.owner = owner;
+        }
+        Self {
+            info,
+            value: Dynamic::UNIT,
+            clear: true,
+            inherited: true,
+        }
+    }
+}
+
+/// Clear slots for all of `properties` (the slots of a parent), on a child owned by `owner`
+fn inherited_slots(properties: &HashMap<String, Property>, owner: ID) -> HashMap<String, Property> {
+    properties
+        .iter()
+        .map(|(name, p)| (name.clone(), p.inherited_by(owner)))
+        .collect()
+}
+
+/// Property of player objects holding their password hash, which only wizards may read
+const PASSWORD_PROPERTY: &str = "password";
+
+/// Properties every object has, which are stored in `Object` fields rather than in `properties`
+const BUILTIN_PROPERTIES: &[&str] = &[
+    "name",
+    "owner",
+    "location",
+    "contents",
+    "programmer",
+    "wizard",
+    "r",
+    "w",
+    "f",
+];
+
+/// Prepositions understood by the command parser, in the same order as LambdaMOO's table.
+/// Each entry lists the alternative spellings of one preposition.
+pub const PREPOSITIONS: &[&[&str]] = &[
+    &["with", "using"],
+    &["at", "to"],
+    &["in front of"],
+    &["in", "inside", "into"],
+    &["on top of", "on", "onto", "upon"],
+    &["out of", "from inside", "from"],
+    &["over"],
+    &["through"],
+    &["under", "underneath", "beneath"],
+    &["behind"],
+    &["beside"],
+    &["for", "about"],
+    &["is"],
+    &["as"],
+    &["off", "off of"],
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VerbPerms {
+    pub r: bool,
+    pub w: bool,
+    pub x: bool,
+    pub d: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerbInfo {
+    pub owner: ID,
+    pub perms: VerbPerms,
+    /// space-separated list of names, each possibly containing a `*` wildcard
+    pub names: String,
+}
+
+impl VerbInfo {
+    pub fn new(owner: ID, perms: VerbPerms, names: String) -> Self {
+        Self {
+            owner,
+            perms,
+            names,
+        }
+    }
+
+    /// Does any of the verb's names match `word`, honoring `*` wildcards the same way LambdaMOO does?
+    pub fn matches(&self, word: &str) -> bool {
+        self.names
+            .split_whitespace()
+            .any(|pattern| verbname_matches(pattern, word))
     }
 }
+
+fn verbname_matches(pattern: &str, word: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let word = word.to_lowercase();
+    if pattern == word {
+        return true;
+    }
+    match pattern.find('*') {
+        None => false,
+        // "foo*" matches anything starting with "foo", "*" matches everything
+        Some(star) if star == pattern.len() - 1 => word.starts_with(&pattern[..star]),
+        // "foo*bar" matches "foo", "foob", "fooba" and "foobar"
+        Some(star) => {
+            let full = pattern.replacen('*', "", 1);
+            word.len() >= star && full.starts_with(&word)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArgSpec {
+    This,
+    Any,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PrepSpec {
+    Any,
+    None,
+    /// index into `PREPOSITIONS`
+    Some(usize),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerbArgs {
+    pub dobj: ArgSpec,
+    pub prep: PrepSpec,
+    pub iobj: ArgSpec,
+}
+
+impl VerbArgs {
+    pub fn new(dobj: ArgSpec, prep: PrepSpec, iobj: ArgSpec) -> Self {
+        Self { dobj, prep, iobj }
+    }
+
+    /// Can a verb with these argument specifiers on `this` handle a command with these objects?
+    fn matches(&self, this: ID, dobj: ID, prep: PrepSpec, iobj: ID) -> bool {
+        self.dobj.matches(this, dobj)
+            && (self.prep == PrepSpec::Any || self.prep == prep)
+            && self.iobj.matches(this, iobj)
+    }
+}
+
+impl ArgSpec {
+    fn matches(&self, this: ID, object: ID) -> bool {
+        match self {
+            ArgSpec::This => object == this,
+            ArgSpec::Any => true,
+            ArgSpec::None => object == -1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Verb {
+    info: VerbInfo,
+    args: VerbArgs,
+    code: String,
+}
+
+impl Verb {
+    fn new(info: VerbInfo, args: VerbArgs) -> Self {
+        Self {
+            info,
+            args,
+            code: String::new(),
+        }
+    }
+
+    pub fn info(&self) -> &VerbInfo {
+        &self.info
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+}
+
+/// Identifies a verb on a single object, either by name or by (zero-based) position.
+#[derive(Debug, Clone)]
+pub enum VerbDesc {
+    Name(String),
+    Index(usize),
+}
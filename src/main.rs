@@ -1,8 +1,14 @@
-use crate::task_context::{SharedTaskContext, TaskContext, TASK_CONTEXT};
+use crate::task_context::{TaskContext, TaskLimits, TASK_CONTEXT};
 use anyhow::Result;
+use api::ObjectProxy;
 use async_channel::{Receiver, Sender};
-use database::{Database, SharedDatabase};
-use rhai::{Engine, Scope};
+use connections::{Connection, Connections, SharedConnections};
+use database::{Database, SharedDatabase, ID, WIZARD};
+use rhai::{Dynamic, Engine, Scope};
+use scheduler::Scheduler;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use structopt::StructOpt;
 use tokio::{
     self,
@@ -11,13 +17,28 @@ use tokio::{
         tcp::{OwnedReadHalf, OwnedWriteHalf},
         TcpListener, TcpStream,
     },
+    sync::{mpsc::UnboundedReceiver, oneshot, watch, Notify},
 };
+use verb_cache::VerbCache;
 
 #[macro_use]
 mod error;
 mod api;
+mod command;
+mod connections;
+mod crypt;
 mod database;
+mod matching;
+mod scheduler;
 mod task_context;
+mod verb_cache;
+
+/// Each connection gets a negative ID, starting below the ones used by Cnothing, Cambiguous_match and
+/// Cfailed_match. Connections that haven't logged in yet are identified by it instead of a player.
+static NEXT_UNLOGGED_IN_ID: AtomicI64 = AtomicI64::new(-4);
+
+/// Told to connections that can't log in because the database has no #0:do_login_command
+const LOGIN_UNAVAILABLE: &str = "*** Login unavailable: #0:do_login_command is missing ***";
 
 #[derive(Debug, StructOpt)]
 struct Opt {
@@ -26,25 +47,62 @@ struct Opt {
 
     #[structopt(default_value = "8888")]
     port: u16,
+
+    /// Maximum number of nested verb calls before E_MAXREC is raised
+    #[structopt(long, default_value = "50")]
+    max_stack_depth: usize,
+
+    /// Number of Rhai operations commands typed by players may run before they're aborted
+    #[structopt(long, default_value = "1000000")]
+    fg_ticks: rhai::INT,
+
+    /// Number of Rhai operations forked and resumed tasks may run before they're aborted
+    #[structopt(long, default_value = "500000")]
+    bg_ticks: rhai::INT,
+
+    /// Number of seconds commands typed by players may run before they're aborted
+    #[structopt(long, default_value = "5")]
+    fg_seconds: u64,
+
+    /// Number of seconds forked and resumed tasks may run before they're aborted
+    #[structopt(long, default_value = "3")]
+    bg_seconds: u64,
+
+    /// Log connections in as the wizard while #0:do_login_command doesn't exist, instead of
+    /// refusing to log them in. Always on when a new database is created.
+    #[structopt(long)]
+    bootstrap: bool,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let opt = Opt::from_args();
+    serve(Opt::from_args()).await
+}
 
+async fn serve(opt: Opt) -> Result<()> {
     let listener = listen(opt.port).await?;
+    let mut bootstrap = opt.bootstrap;
     let database = Database::load(&opt.input_db_file)
-        .map(|db| {
-            eprintln!("Loaded database from {}", opt.input_db_file);
-            db
-        })
+        .inspect(|_| eprintln!("Loaded database from {}", opt.input_db_file))
         .unwrap_or_else(|e| {
             eprintln!("Failed to load database from {}: {}", opt.input_db_file, e);
             eprintln!("Creating new database...");
+            bootstrap = true;
             Database::new()
         })
         .share();
 
+    let connections = Connections::default().share();
+    let scheduler = Scheduler::default().share();
+    let verb_cache = VerbCache::default().share();
+    let engine = Arc::new(new_engine(database.clone(), connections.clone()));
+    let limits = TaskLimits {
+        fg_ticks: opt.fg_ticks,
+        bg_ticks: opt.bg_ticks,
+        fg_seconds: Duration::from_secs(opt.fg_seconds),
+        bg_seconds: Duration::from_secs(opt.bg_seconds),
+    };
+
     let _dump_database_on_drop = DumpDatabaseOnDrop::new(database.clone(), &opt.output_db_file);
 
     let (exit_tx, mut exit_rx) = tokio::sync::mpsc::unbounded_channel::<()>();
@@ -67,11 +125,34 @@ async fn main() -> Result<()> {
                 eprintln!("Exiting...");
                 break;
             },
-            Ok((socket, _)) = listener.accept() => {
-                // TODO login logic goes roughly here
-                let player_id = 1;  // In sync with the wizard object created in Database::new()
-                let context = TaskContext::new(exit_tx.clone(), player_id);
-                handle_connection(socket, database.clone(), context);
+            Ok((socket, peer)) = listener.accept() => {
+                // Without #0:do_login_command (like in a fresh database) there's no way to log in,
+                // so when bootstrapping, connections are logged in as the wizard straight away
+                let connection_id = NEXT_UNLOGGED_IN_ID.fetch_sub(1, Ordering::Relaxed);
+                let can_log_in = database.read().find_callable_verb(0, "do_login_command").is_ok();
+                let player_id = if can_log_in || !bootstrap {
+                    connection_id
+                } else {
+                    WIZARD
+                };
+                let context = TaskContext::new(
+                    exit_tx.clone(),
+                    scheduler.clone(),
+                    verb_cache.clone(),
+                    player_id,
+                    opt.max_stack_depth,
+                    limits,
+                );
+                let name = format!("port {} from {}, port {}", opt.port, peer.ip(), peer.port());
+                handle_connection(
+                    socket,
+                    engine.clone(),
+                    database.clone(),
+                    connections.clone(),
+                    connection_id,
+                    name,
+                    context,
+                );
             }
         }
     }
@@ -79,6 +160,22 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// The engine all the tasks run on. Everything specific to a task, including which player and
+/// connection it's running for, is passed in TASK_CONTEXT.
+fn new_engine(database: SharedDatabase, connections: SharedConnections) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_expr_depths(64, 64);
+    // Every operation counts against the budget of the running task, so runaway code gets aborted
+    engine.on_progress(|_| {
+        TASK_CONTEXT
+            .try_with(|context| context.write().tick())
+            .ok()
+            .flatten()
+    });
+    api::register_api(&mut engine, database, connections);
+    engine
+}
+
 struct DumpDatabaseOnDrop {
     database: SharedDatabase,
     output_db_file: String,
@@ -106,18 +203,39 @@ impl Drop for DumpDatabaseOnDrop {
     }
 }
 
-fn handle_connection(socket: TcpStream, database: SharedDatabase, context: TaskContext) {
+fn handle_connection(
+    socket: TcpStream,
+    engine: Arc<Engine>,
+    database: SharedDatabase,
+    connections: SharedConnections,
+    connection_id: ID,
+    name: String,
+    mut context: TaskContext,
+) {
     tokio::spawn(async move {
         let (read, write) = socket.into_split();
 
-        // MAYBE we can get away with a single engine instance across all the connections?
-        let mut engine = Engine::new();
-        engine.set_max_expr_depths(64, 64);
-        api::register_api(&mut engine, database);
-
+        let (output_tx, output_rx) = tokio::sync::mpsc::unbounded_channel::<String>();
         let (line_tx, line_rx) = async_channel::unbounded::<String>();
-        spawn_read_task(read, line_tx);
-        spawn_processing_task(engine, write, line_rx, context);
+        let connection = Connection::new(connection_id, output_tx, name);
+        let previous = connections
+            .write()
+            .insert(context.connected_player, connection.clone());
+        context.connection = Some(connection.clone());
+        context.engine = Some(engine.clone());
+
+        // Bootstrapping connections are all logged in as the wizard, so a new one takes over the
+        // previous one, like logging in again would
+        if let Some(previous) = previous {
+            previous.send_line("*** Redirecting connection to new port ***");
+            previous.close();
+            let player = context.connected_player;
+            call_hook(&engine, &database, &mut context, "user_reconnected", player).await;
+        }
+
+        spawn_read_task(read, line_tx, connection.closing());
+        spawn_write_task(write, output_rx, connection.closing());
+        spawn_processing_task(engine, database, connections, connection, line_rx, context);
     });
 }
 
@@ -129,51 +247,248 @@ async fn listen(port: u16) -> Result<TcpListener> {
     Ok(listener)
 }
 
-fn spawn_read_task(read: OwnedReadHalf, line_tx: Sender<String>) {
+/// Sends the lines received on the socket to the processing task, until either the client or the
+/// server closes the connection
+fn spawn_read_task(
+    read: OwnedReadHalf,
+    line_tx: Sender<String>,
+    mut closing: watch::Receiver<bool>,
+) {
     let mut lines = BufReader::new(read).lines();
     tokio::spawn(async move {
-        while let Some(line) = lines.next_line().await.unwrap() {
-            line_tx.send(line).await.unwrap();
+        loop {
+            tokio::select! {
+                line = lines.next_line() => match line {
+                    Ok(Some(line)) => {
+                        if line_tx.send(line).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        eprintln!("Failed to read from connection: {}", e);
+                        break;
+                    }
+                },
+                _ = closing.changed() => break,
+            }
         }
     });
 }
 
-fn spawn_processing_task(
-    engine: Engine,
+/// Writes everything sent to the connection to the socket, until all the senders are gone or the
+/// server closes the connection. Tasks still running for the player may hold on to senders, so
+/// closing doesn't wait for them: what was sent so far is written, then the socket is shut down.
+fn spawn_write_task(
     mut write: OwnedWriteHalf,
+    mut output_rx: UnboundedReceiver<String>,
+    mut closing: watch::Receiver<bool>,
+) {
+    tokio::spawn(async move {
+        loop {
+            let text = tokio::select! {
+                biased;
+                _ = closing.changed() => break,
+                text = output_rx.recv() => match text {
+                    Some(text) => text,
+                    None => return,
+                },
+            };
+            if write.write_all(text.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+        while let Ok(text) = output_rx.try_recv() {
+            if write.write_all(text.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+        let _ = write.shutdown().await;
+    });
+}
+
+fn spawn_processing_task(
+    engine: Arc<Engine>,
+    database: SharedDatabase,
+    connections: SharedConnections,
+    connection: Connection,
     line_rx: Receiver<String>,
-    context: TaskContext,
+    mut context: TaskContext,
 ) {
     tokio::spawn(async move {
-        let mut scope = Scope::new();
-        let shared_context = context.shared();
+        // Variables defined on `;` lines carry over to the next ones, unless the line got suspended
+        let mut scope = Some(Scope::new());
+        let mut closing = connection.closing();
 
         loop {
-            let line = match line_rx.recv().await {
-                Ok(l) => l,
-                Err(e) => {
-                    println!("{}", e);
-                    break;
-                }
+            // Lines that arrived before the server closed the connection are dropped
+            let line = tokio::select! {
+                biased;
+                _ = closing.changed() => break,
+                line = line_rx.recv() => match line {
+                    Ok(l) => l,
+                    Err(_) => break,
+                },
             };
 
             println!("< {}", line);
-            if let Some(stripped) = line.strip_prefix(';') {
-                // TODO this will need to move into the core, and we'll just translate to eval() here
-                let code = format!("toliteral(eval({:?}))", stripped);
-                let result = TASK_CONTEXT.sync_scope(shared_context.clone(), || {
-                    engine.eval_with_scope::<String>(&mut scope, &code)
-                });
-                let maybe_msg = match result {
-                    Ok(x) if !x.is_empty() => Some(format!("=> {}\r\n", x)),
-                    Ok(_) => None,
-                    Err(e) => Some(format!("{}\r\n", e)),
-                };
-
-                if let Some(msg) = maybe_msg {
-                    write.write_all(msg.as_bytes()).await.unwrap();
+            connection.touch();
+            // Tasks waiting in read() get the line instead of the command parser
+            let line = match connection.give_to_reader(line) {
+                None => continue,
+                Some(line) => line,
+            };
+            // Each line starts a new task
+            let logged_in = context.connected_player >= 0;
+            if !logged_in
+                && database
+                    .read()
+                    .find_callable_verb(0, "do_login_command")
+                    .is_err()
+            {
+                connection.send_line(LOGIN_UNAVAILABLE);
+            } else if !logged_in {
+                let result = run_task(&mut context, {
+                    let (engine, database, connection) =
+                        (engine.clone(), database.clone(), connection.clone());
+                    move || {
+                        let result = command::do_login_command(&engine, &database, &line);
+                        result.map_err(|e| {
+                            if let Some(message) = scheduler::error_message(&e) {
+                                connection.send_line(&message);
+                            }
+                        })
+                    }
+                })
+                .await;
+                if let Some(Ok(Some(player))) = result {
+                    let previous = connections.write().rename(context.connected_player, player);
+                    context.connected_player = player;
+                    context.task_perms = player;
+                    // Logging in again takes over the player's existing connection
+                    let hook = match previous {
+                        Some(previous) => {
+                            previous.send_line("*** Redirecting connection to new port ***");
+                            previous.close();
+                            connection.send_line("*** Redirected ***");
+                            "user_reconnected"
+                        }
+                        None => {
+                            connection.send_line("*** Connected ***");
+                            "user_connected"
+                        }
+                    };
+                    call_hook(&engine, &database, &mut context, hook, player).await;
                 }
+            } else if let Some(stripped) = line.strip_prefix(';') {
+                let (engine, connection) = (engine.clone(), connection.clone());
+                let code = stripped.to_string();
+                let mut line_scope = scope.take().unwrap_or_default();
+                scope = run_task(&mut context, move || {
+                    let verb_cache = TASK_CONTEXT.with(|context| context.read().verb_cache.clone());
+                    let result = verb_cache::line(&verb_cache, &code, |code| engine.compile(code))
+                        .map_err(Into::into)
+                        .and_then(|ast| {
+                            let value =
+                                engine.eval_ast_with_scope::<Dynamic>(&mut line_scope, &ast)?;
+                            api::toliteral(value)
+                        });
+                    let message = match result {
+                        Ok(x) if !x.is_empty() => Some(format!("=> {}", x)),
+                        Ok(_) => None,
+                        Err(e) => scheduler::error_message(&e),
+                    };
+                    if let Some(message) = message {
+                        connection.send_line(&message);
+                    }
+                    line_scope
+                })
+                .await;
+            } else {
+                let (engine, database, connection) =
+                    (engine.clone(), database.clone(), connection.clone());
+                run_task(&mut context, move || {
+                    let message = match command::execute(&engine, &database, &line) {
+                        Ok(true) => None,
+                        Ok(false) => Some("I couldn't understand that.".to_string()),
+                        Err(e) => scheduler::error_message(&e),
+                    };
+                    if let Some(message) = message {
+                        connection.send_line(&message);
+                    }
+                })
+                .await;
             }
         }
+
+        // A connection that was taken over by a newer one isn't registered anymore, and the player
+        // is still connected
+        let player = context.connected_player;
+        if connections.write().remove(player, connection.id) && player >= 0 {
+            let hook = if connection.is_closing() {
+                "user_disconnected"
+            } else {
+                "user_client_disconnected"
+            };
+            call_hook(&engine, &database, &mut context, hook, player).await;
+        }
+        // Stops the tasks still waiting in read()
+        connection.close();
     });
 }
+
+/// Runs `f` as a new task of the connection, on a thread of its own.
+/// Once the task is done, its context becomes the connection's, so that changes like
+/// set_task_perms() carry over to the next line.
+/// Returns what `f` returned, or `None` if the task suspended first, in which case it goes on in
+/// the background and the connection moves on to its next line.
+async fn run_task<T: Send + 'static>(
+    context: &mut TaskContext,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Option<T> {
+    let detach = Arc::new(Notify::new());
+    let mut task = context.clone();
+    task.task_id = scheduler::next_task_id();
+    task.reset_limits(true);
+    task.detach = Some(detach.clone());
+    let task = task.shared();
+
+    let (done_tx, done_rx) = oneshot::channel();
+    scheduler::spawn_task(task.clone(), move || {
+        let _ = done_tx.send(f());
+    });
+    tokio::select! {
+        biased;
+        done = done_rx => {
+            let result = done.ok()?;
+            *context = TaskContext {
+                detach: None,
+                ..task.read().clone()
+            };
+            Some(result)
+        }
+        _ = detach.notified() => None,
+    }
+}
+
+/// Calls `#0:<name>(player)` if it exists, to let the database react to connection events.
+/// Errors are only logged, since there's no one to show them to.
+async fn call_hook(
+    engine: &Arc<Engine>,
+    database: &SharedDatabase,
+    context: &mut TaskContext,
+    name: &'static str,
+    player: ID,
+) {
+    if database.read().find_callable_verb(0, name).is_err() {
+        return;
+    }
+    let (engine, database) = (engine.clone(), database.clone());
+    run_task(context, move || {
+        let args = vec![Dynamic::from(ObjectProxy::new(player))];
+        if let Err(e) = api::call_verb(&engine, &database, 0, name, args) {
+            eprintln!("Error in #0:{}: {}", name, e);
+        }
+    })
+    .await;
+}
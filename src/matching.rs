@@ -0,0 +1,75 @@
+// Matching object names, like the server does for the direct and indirect objects of commands
+// https://www.sindome.org/moo-manual.html#the-built-in-command-parser
+
+use crate::{
+    api::ObjectProxy,
+    database::{Database, ID},
+};
+
+/// Finds the object called `name` from the point of view of `player`.
+///
+/// Besides the objects in the player's inventory and location (matched by their name or one of their
+/// `aliases`, exactly or by prefix), `name` can be "me", "here" or a literal object number like "#123".
+/// The empty string matches Cnothing. If there's more than one match, the result is Cambiguous_match,
+/// and if there's none, Cfailed_match. Exact matches win over prefix matches.
+pub fn match_object(db: &Database, name: &str, player: ID) -> ID {
+    let name = name.trim();
+    if name.is_empty() {
+        return sentinel(db, "nothing", -1);
+    }
+    if name.eq_ignore_ascii_case("me") {
+        return player;
+    }
+    if name.eq_ignore_ascii_case("here") {
+        return db.location(player);
+    }
+    if let Some(literal) = name.strip_prefix('#') {
+        return match literal.parse() {
+            Ok(id) if db.valid(id) => id,
+            _ => sentinel(db, "failed_match", -3),
+        };
+    }
+
+    let name = name.to_lowercase();
+    let candidates: Vec<(ID, Vec<String>)> = db
+        .contents(player)
+        .iter()
+        .chain(db.contents(db.location(player)))
+        .map(|&o| {
+            let mut names = db.aliases(o);
+            names.extend(db.get_name(o));
+            let names = names.into_iter().map(|n| n.to_lowercase()).collect();
+            (o, names)
+        })
+        .collect();
+
+    let exact: Vec<ID> = candidates
+        .iter()
+        .filter(|(_, names)| names.contains(&name))
+        .map(|(o, _)| *o)
+        .collect();
+    let matches = if exact.is_empty() {
+        candidates
+            .iter()
+            .filter(|(_, names)| names.iter().any(|n| n.starts_with(&name)))
+            .map(|(o, _)| *o)
+            .collect()
+    } else {
+        exact
+    };
+
+    match matches.as_slice() {
+        [] => sentinel(db, "failed_match", -3),
+        [o] => *o,
+        _ => sentinel(db, "ambiguous_match", -2),
+    }
+}
+
+/// The value of the corified property `name` on #0, or `default` if it's missing or not an object
+fn sentinel(db: &Database, name: &str, default: ID) -> ID {
+    db.property_value(0, name)
+        .ok()
+        .and_then(|value| value.clone().try_cast::<ObjectProxy>())
+        .map(|o| o.id())
+        .unwrap_or(default)
+}
@@ -0,0 +1,315 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use parking_lot::RwLock;
+use rhai::{Dynamic, EvalAltResult, FnPtr, NativeCallContext, Position, AST};
+use tokio::{runtime::Handle, sync::oneshot};
+
+use crate::{
+    api::{self, ObjectProxy},
+    database::ID,
+    error::{Error::*, RhaiResult},
+    task_context::{SharedTaskContext, TASK_CONTEXT},
+};
+
+pub type TaskId = rhai::INT;
+
+static NEXT_TASK_ID: AtomicI64 = AtomicI64::new(1);
+
+/// A fresh ID for a task, never reused
+pub fn next_task_id() -> TaskId {
+    NEXT_TASK_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Tokens of the errors that terminate tasks.
+/// Killed tasks stop silently, the others tell the player why.
+const KILLED: &str = "killed";
+pub const OUT_OF_TICKS: &str = "Task ran out of ticks";
+pub const OUT_OF_SECONDS: &str = "Task ran out of seconds";
+
+/// Delays longer than this (about a century) are refused, so adding them to the clocks can't overflow
+const MAX_DELAY: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// Why a suspended task wakes up before its time
+#[derive(Debug)]
+enum Wake {
+    Resume(Dynamic),
+    Kill,
+}
+
+/// A task waiting to be run: either forked and not started yet, or suspended
+#[derive(Debug)]
+pub struct QueuedTask {
+    pub id: TaskId,
+    /// `None` for tasks suspended until they're resumed
+    pub start_at: Option<SystemTime>,
+    /// permissions the task will run with
+    pub programmer: ID,
+    /// the verb that created the task, or -1 and an empty name if it was created outside of verbs
+    pub verb_loc: ID,
+    pub verb_name: String,
+    pub this: ID,
+    /// Wakes the task up, if it's suspended
+    wake: Option<oneshot::Sender<Wake>>,
+}
+
+impl QueuedTask {
+    /// The task in the format of queued_tasks(): [task-id, start-time, x, y, programmer, verb-loc,
+    /// verb-name, line, this]. x, y and the line number have no equivalent here and are always 0.
+    /// The start time of tasks suspended indefinitely is -1.
+    pub fn to_array(&self) -> rhai::Array {
+        let start_time = match self.start_at {
+            None => -1,
+            Some(start_at) => start_at
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as rhai::INT)
+                .unwrap_or(0),
+        };
+        vec![
+            Dynamic::from(self.id),
+            Dynamic::from(start_time),
+            Dynamic::from(0 as rhai::INT),
+            Dynamic::from(0 as rhai::INT),
+            Dynamic::from(ObjectProxy::new(self.programmer)),
+            Dynamic::from(ObjectProxy::new(self.verb_loc)),
+            Dynamic::from(self.verb_name.clone()),
+            Dynamic::from(0 as rhai::INT),
+            Dynamic::from(ObjectProxy::new(self.this)),
+        ]
+    }
+}
+
+/// The tasks waiting to be run, keyed by ID
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    queued: HashMap<TaskId, QueuedTask>,
+}
+
+impl Scheduler {
+    pub fn share(self) -> SharedScheduler {
+        Arc::new(RwLock::new(self))
+    }
+
+    /// The queued tasks running with the permissions of `programmer`, or all of them if `all` is true,
+    /// the ones starting soonest first and the ones suspended indefinitely last
+    pub fn list(&self, programmer: ID, all: bool) -> Vec<&QueuedTask> {
+        let mut tasks: Vec<&QueuedTask> = self
+            .queued
+            .values()
+            .filter(|task| all || task.programmer == programmer)
+            .collect();
+        tasks.sort_by_key(|task| (task.start_at.is_none(), task.start_at, task.id));
+        tasks
+    }
+
+    pub fn get(&self, id: TaskId) -> Option<&QueuedTask> {
+        self.queued.get(&id)
+    }
+
+    /// Wakes up the suspended task `id`, making suspend() return `value`.
+    /// Returns `false` if there's no such suspended task.
+    pub fn resume(&mut self, id: TaskId, value: Dynamic) -> bool {
+        match self.queued.get(&id) {
+            Some(task) if task.wake.is_some() => (),
+            _ => return false,
+        }
+        let task = self.remove(id).unwrap();
+        // The task may have timed out in the meantime, it just won't see the value then
+        let _ = task.wake.unwrap().send(Wake::Resume(value));
+        true
+    }
+
+    /// Removes the task `id`, so it never runs or, if it's suspended, stops right away.
+    /// Returns `false` if there's no such task.
+    pub fn kill(&mut self, id: TaskId) -> bool {
+        match self.remove(id) {
+            None => false,
+            Some(task) => {
+                if let Some(wake) = task.wake {
+                    let _ = wake.send(Wake::Kill);
+                }
+                true
+            }
+        }
+    }
+
+    fn insert(&mut self, task: QueuedTask) {
+        self.queued.insert(task.id, task);
+    }
+
+    fn remove(&mut self, id: TaskId) -> Option<QueuedTask> {
+        self.queued.remove(&id)
+    }
+}
+
+pub type SharedScheduler = Arc<RwLock<Scheduler>>;
+
+/// Queues a new task that calls `f` after `delay`, with a copy of the current task's context.
+/// Errors raised by the new task are reported to the player's connection.
+/// Returns the ID of the new task.
+pub fn fork(ctx: &NativeCallContext, delay: Duration, f: FnPtr) -> RhaiResult<TaskId> {
+    // Closures are compiled into the script that defines them, so keep that around to call it later
+    let ast = ctx
+        .namespaces()
+        .iter()
+        .find(|lib| lib.get_script_fn(f.fn_name(), f.curry().len()).is_some())
+        .map(|lib| AST::new_from_module(lib.clone()))
+        .unwrap_or_else(AST::empty);
+
+    let start_at = start_at(delay)?;
+    let id = next_task_id();
+    let mut context = TASK_CONTEXT.with(|context| context.read().clone());
+    let engine = match context.engine.clone() {
+        None => bail!(E_INVARG),
+        Some(engine) => engine,
+    };
+    context.task_id = id;
+    context.detach = None;
+    // The forked task starts out in the verb that forked it, whose callers have moved on since
+    context.frames = context.frames.last().cloned().into_iter().collect();
+
+    let scheduler = context.scheduler.clone();
+    let frame = context.frames.last();
+    let verb = frame.map(|frame| (frame.definer, frame.verb.clone()));
+    scheduler.write().insert(QueuedTask {
+        id,
+        start_at: Some(start_at),
+        programmer: context.task_perms,
+        verb_loc: frame.map(|frame| frame.definer).unwrap_or(-1),
+        verb_name: frame.map(|frame| frame.verb.clone()).unwrap_or_default(),
+        this: frame.map(|frame| frame.this).unwrap_or(-1),
+        wake: None,
+    });
+
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        if scheduler.write().remove(id).is_none() {
+            return;
+        }
+        let connection = context.connection.clone();
+        context.reset_limits(false);
+        spawn_task(context.shared(), move || {
+            let result = f
+                .call::<Dynamic>(&engine, &ast, ())
+                .map_err(|e| match &verb {
+                    Some((definer, name)) => api::verb_error(e, *definer, name),
+                    None => e,
+                });
+            let message = result.err().and_then(|e| error_message(&e));
+            if let (Some(message), Some(connection)) = (message, connection) {
+                connection.send_line(&message);
+            }
+        });
+    });
+
+    Ok(id)
+}
+
+/// Runs `f` as the task with the given context, on a thread of its own, so it can block while it's
+/// suspended without holding up anything else.
+/// Rhai can't save the state of a running script, so a suspended task keeps its thread until it
+/// finishes.
+pub fn spawn_task(context: SharedTaskContext, f: impl FnOnce() + Send + 'static) {
+    let runtime = Handle::current();
+    // Nested verb calls recurse on the native stack, and the default 2MiB only fits about 40 of them
+    // in a debug build, so leave enough room for the configured maximum depth.
+    let stack_size = (2 + context.read().max_stack_depth / 4) * 1024 * 1024;
+    let spawned = thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(move || {
+            let _runtime = runtime.enter();
+            TASK_CONTEXT.sync_scope(context, f)
+        });
+    if let Err(e) = spawned {
+        eprintln!("Failed to start a task: {}", e);
+    }
+}
+
+/// Suspends the current task for `delay`, or until it's resumed if there's no delay, blocking the
+/// task's thread in the meantime. Whoever waits for the task, like the connection that started it,
+/// moves on without it.
+/// Returns the value given to resume(), or 0 if the delay ran out. Fails with an uncatchable
+/// error if the task is killed.
+pub fn suspend(delay: Option<Duration>) -> RhaiResult<Dynamic> {
+    let start_at = delay.map(start_at).transpose()?;
+    let (id, scheduler, task) = TASK_CONTEXT.with(|context| {
+        let context = context.read();
+        let frame = context.frames.last();
+        let task = QueuedTask {
+            id: context.task_id,
+            start_at,
+            programmer: context.task_perms,
+            verb_loc: frame.map(|frame| frame.definer).unwrap_or(-1),
+            verb_name: frame.map(|frame| frame.verb.clone()).unwrap_or_default(),
+            this: frame.map(|frame| frame.this).unwrap_or(-1),
+            wake: None,
+        };
+        (context.task_id, context.scheduler.clone(), task)
+    });
+
+    let (wake_tx, wake_rx) = oneshot::channel();
+    scheduler.write().insert(QueuedTask {
+        wake: Some(wake_tx),
+        ..task
+    });
+    TASK_CONTEXT.with(|context| context.write().detach());
+    let wake = Handle::current().block_on(async {
+        match delay {
+            None => wake_rx.await.ok(),
+            Some(delay) => tokio::select! {
+                wake = wake_rx => wake.ok(),
+                _ = tokio::time::sleep(delay) => None,
+            },
+        }
+    });
+    scheduler.write().remove(id);
+    TASK_CONTEXT.with(|context| context.write().reset_limits(false));
+
+    match wake {
+        None => Ok(Dynamic::ZERO),
+        Some(Wake::Resume(value)) => Ok(value),
+        Some(Wake::Kill) => Err(killed()),
+    }
+}
+
+/// When a task delayed by `delay` starts. Fails with E_INVARG if the delay is too long.
+fn start_at(delay: Duration) -> RhaiResult<SystemTime> {
+    match SystemTime::now().checked_add(delay) {
+        Some(start_at) if delay <= MAX_DELAY => Ok(start_at),
+        _ => bail!(E_INVARG),
+    }
+}
+
+/// The error that stops a killed task
+pub fn killed() -> Box<EvalAltResult> {
+    Box::new(EvalAltResult::ErrorTerminated(
+        KILLED.into(),
+        Position::NONE,
+    ))
+}
+
+/// What to tell the player about the error that stopped a task, if anything
+pub fn error_message(e: &EvalAltResult) -> Option<String> {
+    match termination_reason(e).as_deref() {
+        Some(KILLED) => None,
+        Some(reason) => Some(reason.to_string()),
+        None => Some(e.to_string()),
+    }
+}
+
+/// The token of the error that terminated a task
+fn termination_reason(e: &EvalAltResult) -> Option<rhai::ImmutableString> {
+    match e {
+        EvalAltResult::ErrorTerminated(token, _) => token.clone().into_immutable_string().ok(),
+        // eval() wraps everything, even termination
+        EvalAltResult::ErrorInFunctionCall(_, _, e, _) => termination_reason(e),
+        _ => None,
+    }
+}
@@ -1,21 +1,105 @@
-use crate::database::ID;
+use crate::{
+    command::Command,
+    connections::Connection,
+    database::ID,
+    scheduler::{self, SharedScheduler, TaskId},
+    verb_cache::SharedVerbCache,
+};
 use parking_lot::RwLock;
-use std::sync::Arc;
+use rhai::{Dynamic, Engine};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
 
 #[derive(Debug, Clone)]
 pub struct TaskContext {
+    #[allow(dead_code)]
     pub exit_tx: ExitSender,
+    pub task_id: TaskId,
     pub connected_player: ID,
     pub task_perms: ID,
+    /// verbs currently being executed, innermost last
+    pub frames: Vec<Frame>,
+    /// calling a verb with this many verbs already running raises E_MAXREC
+    pub max_stack_depth: usize,
+    /// the command being executed, its parts are available to all verbs called while executing it
+    pub command: Command,
+    /// the connection the task was started from, which read() reads from
+    pub connection: Option<Connection>,
+    /// the engine running the task, which forked tasks run on as well
+    pub engine: Option<Arc<Engine>>,
+    pub scheduler: SharedScheduler,
+    pub verb_cache: SharedVerbCache,
+    pub limits: TaskLimits,
+    /// operations the task may still run before it's aborted
+    pub ticks_left: rhai::INT,
+    /// when the task is aborted if it's still running
+    pub deadline: Instant,
+    /// notified when the task suspends, so the connection that started it can move on to the next
+    /// line instead of waiting for it to finish
+    pub detach: Option<Arc<Notify>>,
 }
 
 impl TaskContext {
     #[must_use]
-    pub fn new(exit_tx: ExitSender, player: ID) -> Self {
+    pub fn new(
+        exit_tx: ExitSender,
+        scheduler: SharedScheduler,
+        verb_cache: SharedVerbCache,
+        player: ID,
+        max_stack_depth: usize,
+        limits: TaskLimits,
+    ) -> Self {
         Self {
             exit_tx,
+            task_id: 0,
             connected_player: player,
             task_perms: player,
+            frames: Vec::new(),
+            max_stack_depth,
+            command: Command::default(),
+            connection: None,
+            engine: None,
+            scheduler,
+            verb_cache,
+            limits,
+            ticks_left: limits.fg_ticks,
+            deadline: Instant::now() + limits.fg_seconds,
+            detach: None,
+        }
+    }
+
+    /// Lets whoever is waiting for the task to finish carry on without it
+    pub fn detach(&mut self) {
+        if let Some(detach) = self.detach.take() {
+            detach.notify_one();
+        }
+    }
+
+    /// Gives the task a fresh budget of ticks and seconds. Commands typed by players run in the
+    /// foreground, forked and resumed tasks in the background.
+    pub fn reset_limits(&mut self, foreground: bool) {
+        let (ticks, seconds) = if foreground {
+            (self.limits.fg_ticks, self.limits.fg_seconds)
+        } else {
+            (self.limits.bg_ticks, self.limits.bg_seconds)
+        };
+        self.ticks_left = ticks;
+        self.deadline = Instant::now() + seconds;
+    }
+
+    /// Counts one operation against the budget of the task.
+    /// Returns the reason to abort the task if it's exceeded, for Engine::on_progress.
+    pub fn tick(&mut self) -> Option<Dynamic> {
+        self.ticks_left -= 1;
+        if self.ticks_left < 0 {
+            Some(scheduler::OUT_OF_TICKS.into())
+        } else if Instant::now() >= self.deadline {
+            Some(scheduler::OUT_OF_SECONDS.into())
+        } else {
+            None
         }
     }
 
@@ -24,6 +108,29 @@ impl TaskContext {
     }
 }
 
+/// How much a task may do before it's aborted, in Rhai operations ("ticks") and in time
+#[derive(Debug, Clone, Copy)]
+pub struct TaskLimits {
+    pub fg_ticks: rhai::INT,
+    pub bg_ticks: rhai::INT,
+    pub fg_seconds: Duration,
+    pub bg_seconds: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub this: ID,
+    /// name the verb was called by
+    pub verb: String,
+    /// permissions the verb runs with
+    pub programmer: ID,
+    /// permissions of the code that called the verb, restored when the verb returns
+    pub caller_perms: ID,
+    /// the object the running verb is defined on, so that `pass` knows where to continue the lookup
+    pub definer: ID,
+    pub player: ID,
+}
+
 pub type ExitSender = tokio::sync::mpsc::UnboundedSender<()>;
 pub type SharedTaskContext = Arc<RwLock<TaskContext>>;
 
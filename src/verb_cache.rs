@@ -0,0 +1,105 @@
+use std::{collections::HashMap, sync::Arc};
+
+use parking_lot::Mutex;
+use rhai::{ParseError, AST};
+
+use crate::database::ID;
+
+/// Lines typed after `;` are cached as well, but there's no telling which ones will be typed again,
+/// so the cache forgets them all once there are this many
+const MAX_LINES: usize = 1000;
+
+/// Compiled code of verbs and `;` lines, so it isn't parsed again every time it runs.
+///
+/// Verbs are cached per verb (the object defining it and its names) along with their code, which
+/// is compared on every lookup, so changing the code replaces the entry. Compiled code doesn't
+/// depend on where the verb was found, so changing parents doesn't affect the entries.
+#[derive(Debug, Default)]
+pub struct VerbCache {
+    verbs: HashMap<(ID, String), (String, Arc<AST>)>,
+    lines: HashMap<String, Arc<AST>>,
+    hits: u64,
+    misses: u64,
+}
+
+impl VerbCache {
+    pub fn share(self) -> SharedVerbCache {
+        Arc::new(Mutex::new(self))
+    }
+
+    /// Number of lookups that found compiled code, number of lookups that didn't, and number of
+    /// entries
+    pub fn stats(&self) -> (u64, u64, usize) {
+        (self.hits, self.misses, self.verbs.len() + self.lines.len())
+    }
+
+    /// Forgets the verbs defined on `id`
+    pub fn invalidate(&mut self, id: ID) {
+        self.verbs.retain(|(definer, _), _| *definer != id);
+    }
+
+    fn get_verb(&mut self, key: &(ID, String), code: &str) -> Option<Arc<AST>> {
+        let ast = match self.verbs.get(key) {
+            Some((c, ast)) if c == code => Some(ast.clone()),
+            _ => None,
+        };
+        self.count(ast.is_some());
+        ast
+    }
+
+    fn get_line(&mut self, code: &str) -> Option<Arc<AST>> {
+        let ast = self.lines.get(code).cloned();
+        self.count(ast.is_some());
+        ast
+    }
+
+    fn count(&mut self, hit: bool) {
+        if hit {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+    }
+}
+
+pub type SharedVerbCache = Arc<Mutex<VerbCache>>;
+
+/// The compiled code of the verb called `names` on `definer`, compiling it with `compile` if it
+/// isn't cached yet.
+/// The cache isn't locked while compiling, so other tasks may end up compiling the same code.
+pub fn verb(
+    cache: &SharedVerbCache,
+    definer: ID,
+    names: &str,
+    code: &str,
+    compile: impl FnOnce(&str) -> Result<AST, ParseError>,
+) -> Result<Arc<AST>, ParseError> {
+    let key = (definer, names.to_string());
+    if let Some(ast) = cache.lock().get_verb(&key, code) {
+        return Ok(ast);
+    }
+    let ast = Arc::new(compile(code)?);
+    cache
+        .lock()
+        .verbs
+        .insert(key, (code.to_string(), ast.clone()));
+    Ok(ast)
+}
+
+/// The compiled code of a `;` line, compiling it with `compile` if it isn't cached yet
+pub fn line(
+    cache: &SharedVerbCache,
+    code: &str,
+    compile: impl FnOnce(&str) -> Result<AST, ParseError>,
+) -> Result<Arc<AST>, ParseError> {
+    if let Some(ast) = cache.lock().get_line(code) {
+        return Ok(ast);
+    }
+    let ast = Arc::new(compile(code)?);
+    let mut cache = cache.lock();
+    if cache.lines.len() >= MAX_LINES {
+        cache.lines.clear();
+    }
+    cache.lines.insert(code.to_string(), ast.clone());
+    Ok(ast)
+}